@@ -6,7 +6,7 @@ use std::collections::HashMap;
 
 use crate::plugins::ImagesPluginData;
 use egui::Context;
-use walkers::{Map, MapMemory, Tiles};
+use walkers::{Downloader, HttpOptions, Map, MapMemory, Tiles};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Provider {
@@ -19,14 +19,28 @@ pub enum Provider {
 fn providers(egui_ctx: Context) -> HashMap<Provider, Tiles> {
     let mut providers = HashMap::default();
 
+    // All providers share a single IO thread and HTTP client.
+    let http_options = HttpOptions {
+        downloader: Some(Downloader::new()),
+        ..Default::default()
+    };
+
     providers.insert(
         Provider::OpenStreetMap,
-        Tiles::new(walkers::providers::OpenStreetMap, egui_ctx.to_owned()),
+        Tiles::with_options(
+            walkers::providers::OpenStreetMap,
+            http_options.clone(),
+            egui_ctx.to_owned(),
+        ),
     );
 
     providers.insert(
         Provider::Geoportal,
-        Tiles::new(walkers::providers::Geoportal, egui_ctx.to_owned()),
+        Tiles::with_options(
+            walkers::providers::Geoportal,
+            http_options.clone(),
+            egui_ctx.to_owned(),
+        ),
     );
 
     // Pass in a mapbox access token at compile time. May or may not be what you want to do,
@@ -37,24 +51,26 @@ fn providers(egui_ctx: Context) -> HashMap<Provider, Tiles> {
     if let Some(token) = mapbox_access_token {
         providers.insert(
             Provider::MapboxStreets,
-            Tiles::new(
+            Tiles::with_options(
                 walkers::providers::Mapbox {
                     style: walkers::providers::MapboxStyle::Streets,
                     access_token: token.to_string(),
                     high_resolution: false,
                 },
+                http_options.clone(),
                 egui_ctx.to_owned(),
             ),
         );
 
         providers.insert(
             Provider::MapboxSatellite,
-            Tiles::new(
+            Tiles::with_options(
                 walkers::providers::Mapbox {
                     style: walkers::providers::MapboxStyle::Satellite,
                     access_token: token.to_string(),
                     high_resolution: true,
                 },
+                http_options,
                 egui_ctx.to_owned(),
             ),
         );
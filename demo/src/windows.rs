@@ -15,7 +15,7 @@ pub fn acknowledge(ui: &Ui, attribution: Attribution) {
                 if let Some(logo) = attribution.logo_light {
                     ui.add(egui::Image::new(logo).max_height(30.0).max_width(80.0));
                 }
-                ui.hyperlink_to(attribution.text, attribution.url);
+                ui.hyperlink_to(attribution.text.as_ref(), attribution.url);
             });
         });
 }
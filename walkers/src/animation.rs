@@ -0,0 +1,258 @@
+//! Smooth transitions of the map's center and zoom, see [`crate::MapMemory::ease_to`] and
+//! [`crate::MapMemory::fly_to`].
+use std::time::Duration;
+
+use egui::Vec2;
+use web_time::Instant;
+
+use crate::{mercator::screen_to_position, Position};
+
+/// How the progress of an animation changes over time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+
+    /// Start slowly, then accelerate.
+    EaseIn,
+
+    /// Start fast, then slow down.
+    EaseOut,
+
+    /// Start slowly, accelerate, then slow down before the end.
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map linear progress, from 0 to 1, into the eased one.
+    fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t.powi(3),
+            Easing::EaseOut => 1. - (1. - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4. * t.powi(3)
+                } else {
+                    1. - (2. - 2. * t).powi(3) / 2.
+                }
+            }
+        }
+    }
+}
+
+/// Center and zoom of the map.
+pub(crate) type View = (Position, f64);
+
+#[derive(Debug, Clone, Copy)]
+enum Path {
+    /// Center and zoom are interpolated independently.
+    Straight,
+
+    /// Zoom out in the middle of the way, see [`fly`].
+    Fly,
+}
+
+/// Transition between the current view and the target one, in progress.
+#[derive(Debug, Clone)]
+pub(crate) struct Animation {
+    path: Path,
+    easing: Easing,
+
+    /// It is known only in the first frame, as the map might be following `my_position`.
+    from: Option<View>,
+
+    /// `None` keeps the center where it was.
+    to: (Option<Position>, f64),
+
+    started: Instant,
+    duration: Duration,
+}
+
+impl Animation {
+    pub(crate) fn ease(
+        position: Option<Position>,
+        zoom: f64,
+        duration: Duration,
+        easing: Easing,
+    ) -> Self {
+        Self {
+            path: Path::Straight,
+            easing,
+            from: None,
+            to: (position, zoom),
+            started: Instant::now(),
+            duration,
+        }
+    }
+
+    pub(crate) fn fly(position: Position, zoom: f64, duration: Duration) -> Self {
+        Self {
+            path: Path::Fly,
+            ..Self::ease(Some(position), zoom, duration, Easing::EaseInOut)
+        }
+    }
+
+    /// Whether the animation changes the center, or only the zoom.
+    pub(crate) fn moves_center(&self) -> bool {
+        self.to.0.is_some()
+    }
+
+    /// View in the current frame, and whether the animation is finished. `current` is the view
+    /// before the animation started, and `viewport` is the size of the map widget.
+    pub(crate) fn step(&mut self, current: View, viewport: Vec2) -> (View, bool) {
+        let progress = if self.duration.is_zero() {
+            1.
+        } else {
+            self.started.elapsed().as_secs_f64() / self.duration.as_secs_f64()
+        };
+
+        (self.at(progress, current, viewport), progress >= 1.)
+    }
+
+    fn at(&mut self, progress: f64, current: View, viewport: Vec2) -> View {
+        let (from, from_zoom) = *self.from.get_or_insert(current);
+        let (to, to_zoom) = (self.to.0.unwrap_or(from), self.to.1);
+
+        if progress >= 1. {
+            return (to, to_zoom);
+        }
+
+        // Everything is calculated in pixels of the initial zoom.
+        let start = from.project(from_zoom);
+        let end = to.project(from_zoom);
+        let t = self.easing.apply(progress.max(0.));
+
+        let (traveled, zoom) = match self.path {
+            Path::Straight => (t, from_zoom + (to_zoom - from_zoom) * t),
+            Path::Fly => {
+                let width = viewport.max_elem().max(1.) as f64;
+                let delta = end - start;
+                let distance = delta.x().hypot(delta.y());
+                let (traveled, visible) =
+                    fly(distance, width, width / 2f64.powf(to_zoom - from_zoom), t);
+                (traveled, from_zoom + (width / visible).log2())
+            }
+        };
+
+        (
+            screen_to_position(start + (end - start) * traveled, from_zoom),
+            zoom,
+        )
+    }
+}
+
+/// Curvature of the path. Bigger values zoom out more.
+const RHO: f64 = 1.42;
+
+/// Point of the optimal path between two views, as described in "Smooth and efficient zooming
+/// and panning" by Jarke J. van Wijk and Wim A.A. Nuij. Views are given by the `distance`
+/// between their centers and their widths, all in the same units. Returns the fraction of the
+/// distance traveled at `t` (from 0 to 1), and the width of the view at that moment.
+fn fly(distance: f64, start_width: f64, end_width: f64, t: f64) -> (f64, f64) {
+    let (w0, w1) = (start_width, end_width);
+    let rho2 = RHO * RHO;
+
+    // Centers are the same, so there is only zooming.
+    if distance < 1e-6 {
+        let length = (w1 / w0).ln() / RHO;
+        return (t, w0 * (RHO * t * length).exp());
+    }
+
+    // `asinh` is the same as the paper's `ln(sqrt(b^2 + 1) - b)`, with the sign flipped, but
+    // it does not lose precision for long distances.
+    let b = |w: f64, sign: f64| {
+        (w1 * w1 - w0 * w0 + sign * rho2 * rho2 * distance * distance) / (2. * w * rho2 * distance)
+    };
+    let r0 = -b(w0, 1.).asinh();
+    let r1 = -b(w1, -1.).asinh();
+
+    let s = t * (r1 - r0) / RHO;
+    let traveled = w0 / (rho2 * distance) * (r0.cosh() * (RHO * s + r0).tanh() - r0.sinh());
+    (traveled, w0 * r0.cosh() / (RHO * s + r0).cosh())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(0., easing.apply(0.));
+            assert_eq!(1., easing.apply(1.));
+        }
+
+        assert_eq!(0.5, Easing::EaseInOut.apply(0.5));
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+    }
+
+    #[test]
+    fn flying_zooms_out_in_the_middle() {
+        let (distance, width) = (100_000., 1000.);
+
+        let (traveled, visible) = fly(distance, width, width, 0.);
+        approx::assert_relative_eq!(0., traveled, epsilon = 1e-9);
+        approx::assert_relative_eq!(width, visible, epsilon = 1e-6);
+
+        let (traveled, visible) = fly(distance, width, width, 0.5);
+        approx::assert_relative_eq!(0.5, traveled, epsilon = 1e-9);
+        assert!(visible > 10. * width);
+
+        let (traveled, visible) = fly(distance, width, width, 1.);
+        approx::assert_relative_eq!(1., traveled, epsilon = 1e-9);
+        approx::assert_relative_eq!(width, visible, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn flying_without_moving_only_zooms() {
+        let (traveled, visible) = fly(0., 1000., 250., 0.5);
+        assert_eq!(0.5, traveled);
+        approx::assert_relative_eq!(500., visible, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn animation_goes_from_current_to_target_view() {
+        let from = Position::from_lon_lat(17.03664, 51.09916);
+        let to = Position::from_lon_lat(21.00027, 52.26470);
+        let viewport = Vec2::new(800., 600.);
+
+        for mut animation in [
+            Animation::ease(Some(to), 14., Duration::from_secs(1), Easing::Linear),
+            Animation::fly(to, 14., Duration::from_secs(1)),
+        ] {
+            let (position, zoom) = animation.at(0., (from, 10.), viewport);
+            approx::assert_relative_eq!(from.lon(), position.lon(), epsilon = 1e-9);
+            approx::assert_relative_eq!(from.lat(), position.lat(), epsilon = 1e-9);
+            approx::assert_relative_eq!(10., zoom, epsilon = 1e-9);
+
+            // Initial view is remembered, so the current one does not matter anymore.
+            let (_, zoom) = animation.at(0.5, (to, 3.), viewport);
+            assert!(zoom < 14.);
+
+            assert_eq!((to, 14.), animation.at(1., (to, 3.), viewport));
+        }
+    }
+
+    #[test]
+    fn zooming_keeps_the_center() {
+        let center = Position::from_lon_lat(17.03664, 51.09916);
+        let mut animation = Animation::ease(None, 12., Duration::from_secs(1), Easing::Linear);
+
+        let (position, zoom) = animation.at(0.5, (center, 10.), Vec2::new(800., 600.));
+        approx::assert_relative_eq!(center.lon(), position.lon(), epsilon = 1e-9);
+        approx::assert_relative_eq!(center.lat(), position.lat(), epsilon = 1e-9);
+        approx::assert_relative_eq!(11., zoom, epsilon = 1e-9);
+
+        let (_, finished) = Animation::ease(None, 12., Duration::ZERO, Easing::Linear)
+            .step((center, 10.), Vec2::new(800., 600.));
+        assert!(finished);
+    }
+}
@@ -0,0 +1,269 @@
+//! [Bing Maps](https://learn.microsoft.com/en-us/bingmaps/rest-services/imagery/get-imagery-metadata)
+//! imagery, which is addressed with quadkeys. Tile URLs are not fixed - they are obtained from
+//! the imagery metadata service, using an API key.
+use std::ops::RangeInclusive;
+
+use serde::Deserialize;
+
+use crate::{
+    download::{download_document, HttpOptions},
+    fetcher::FetchError,
+    mercator::TileId,
+    providers::{Attribution, TileSource},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Http(reqwest::Error),
+
+    #[error(transparent)]
+    Json(serde_json::Error),
+
+    #[error(transparent)]
+    Credentials(FetchError),
+
+    #[error("imagery metadata does not contain any resource")]
+    NoResources,
+}
+
+/// Kinds of Bing Maps imagery.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImagerySet {
+    #[default]
+    Aerial,
+    AerialWithLabels,
+    Road,
+    CanvasDark,
+    CanvasLight,
+    CanvasGray,
+}
+
+impl ImagerySet {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Aerial => "Aerial",
+            Self::AerialWithLabels => "AerialWithLabelsOnDemand",
+            Self::Road => "RoadOnDemand",
+            Self::CanvasDark => "CanvasDark",
+            Self::CanvasLight => "CanvasLight",
+            Self::CanvasGray => "CanvasGray",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    copyright: Option<String>,
+    resource_sets: Vec<ResourceSet>,
+}
+
+#[derive(Deserialize)]
+struct ResourceSet {
+    resources: Vec<Resource>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Resource {
+    image_url: String,
+    image_url_subdomains: Option<Vec<String>>,
+    image_width: Option<u32>,
+    zoom_min: Option<u8>,
+    zoom_max: Option<u8>,
+}
+
+/// Bing Maps imagery. Pass it to [`crate::Tiles::new`].
+#[derive(Clone)]
+pub struct BingMaps {
+    /// URL with `{quadkey}`, `{subdomain}` and `{culture}` placeholders.
+    pub image_url: String,
+
+    /// Values of `{subdomain}`. Each tile always gets the same one, so it can be cached.
+    pub subdomains: Vec<String>,
+
+    /// Language of the labels, such as `en-US`.
+    pub culture: String,
+
+    /// Taken from the metadata's copyright notice.
+    pub attribution: Attribution,
+
+    pub zoom_range: RangeInclusive<u8>,
+
+    /// Size of each tile, should be a multiple of 256
+    pub tile_size: u32,
+}
+
+/// URL of the imagery metadata service, with the key escaped.
+fn metadata_url(imagery_set: ImagerySet, key: &str) -> reqwest::Url {
+    reqwest::Url::parse_with_params(
+        &format!(
+            "https://dev.virtualearth.net/REST/v1/Imagery/Metadata/{}",
+            imagery_set.name()
+        ),
+        [("uriScheme", "https"), ("key", key)],
+    )
+    .expect("metadata URL is always valid, only its parameters vary")
+}
+
+impl BingMaps {
+    /// Obtain the tile URLs from the imagery metadata service, with the client given by the
+    /// options.
+    pub async fn new(
+        imagery_set: ImagerySet,
+        key: &str,
+        http_options: &HttpOptions,
+    ) -> Result<Self, Error> {
+        Self::from_metadata_url(metadata_url(imagery_set, key).as_str(), http_options).await
+    }
+
+    /// Like [`BingMaps::new`], but with a complete URL of the metadata, including the key.
+    pub async fn from_metadata_url(url: &str, http_options: &HttpOptions) -> Result<Self, Error> {
+        let json = download_document(url, http_options, Error::Http, Error::Credentials).await?;
+        Self::parse_metadata(&json)
+    }
+
+    /// Create the source from an already downloaded metadata document.
+    pub fn parse_metadata(json: &str) -> Result<Self, Error> {
+        let metadata: Metadata = serde_json::from_str(json).map_err(Error::Json)?;
+        let resource = metadata
+            .resource_sets
+            .into_iter()
+            .flat_map(|resource_set| resource_set.resources)
+            .next()
+            .ok_or(Error::NoResources)?;
+
+        Ok(Self {
+            image_url: resource.image_url,
+            subdomains: resource.image_url_subdomains.unwrap_or_default(),
+            culture: "en-US".to_owned(),
+            attribution: Attribution {
+                text: metadata
+                    .copyright
+                    .unwrap_or_else(|| "Microsoft".to_owned())
+                    .into(),
+                url: "https://www.microsoft.com/maps/product/terms.html".into(),
+                ..Default::default()
+            },
+            zoom_range: resource.zoom_min.unwrap_or(1)..=resource.zoom_max.unwrap_or(19),
+            tile_size: resource.image_width.unwrap_or(256),
+        })
+    }
+}
+
+impl TileSource for BingMaps {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        let mut url = self
+            .image_url
+            .replace("{quadkey}", &tile_id.quadkey())
+            .replace("{culture}", &self.culture);
+
+        if !self.subdomains.is_empty() {
+            let index = (tile_id.x as usize + tile_id.y as usize) % self.subdomains.len();
+            url = url.replace("{subdomain}", &self.subdomains[index]);
+        }
+
+        url
+    }
+
+    fn attribution(&self) -> Attribution {
+        self.attribution.clone()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.zoom_range.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::block_on;
+
+    /// Trimmed response of the metadata service.
+    const METADATA: &str = r#"{
+        "authenticationResultCode": "ValidCredentials",
+        "copyright": "Copyright © 2023 Microsoft and its suppliers.",
+        "resourceSets": [{
+            "estimatedTotal": 1,
+            "resources": [{
+                "__type": "ImageryMetadata:http://schemas.microsoft.com/search/local/ws/rest/v1",
+                "imageHeight": 256,
+                "imageUrl": "https://ecn.{subdomain}.tiles.virtualearth.net/tiles/a{quadkey}.jpeg?g=14041&mkt={culture}",
+                "imageUrlSubdomains": ["t0", "t1", "t2", "t3"],
+                "imageWidth": 256,
+                "zoomMax": 21,
+                "zoomMin": 1
+            }]
+        }],
+        "statusCode": 200
+    }"#;
+
+    #[test]
+    fn metadata_is_parsed() {
+        let source = BingMaps::parse_metadata(METADATA).unwrap();
+
+        assert_eq!(1..=21, source.zoom_range());
+        assert_eq!(256, source.tile_size());
+        assert_eq!(
+            "Copyright © 2023 Microsoft and its suppliers.",
+            source.attribution().text
+        );
+
+        let tile_id = TileId {
+            x: 3,
+            y: 5,
+            zoom: 3,
+        };
+        assert_eq!(
+            "https://ecn.t0.tiles.virtualearth.net/tiles/a213.jpeg?g=14041&mkt=en-US",
+            source.tile_url(tile_id)
+        );
+    }
+
+    #[test]
+    fn metadata_without_resources_is_rejected() {
+        assert!(matches!(
+            BingMaps::parse_metadata(r#"{"resourceSets": []}"#),
+            Err(Error::NoResources)
+        ));
+    }
+
+    #[test]
+    fn key_is_escaped() {
+        assert_eq!(
+            "https://dev.virtualearth.net/REST/v1/Imagery/Metadata/RoadOnDemand\
+            ?uriScheme=https&key=a%2Bb%26c%3Dd",
+            metadata_url(ImagerySet::Road, "a+b&c=d").as_str()
+        );
+    }
+
+    #[test]
+    fn metadata_is_downloaded() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("GET", "/REST/v1/Imagery/Metadata/Aerial")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "key".to_owned(),
+                "secret".to_owned(),
+            ))
+            .with_body(METADATA)
+            .create();
+
+        let source = block_on(BingMaps::from_metadata_url(
+            &format!(
+                "{}/REST/v1/Imagery/Metadata/Aerial?key=secret",
+                server.url()
+            ),
+            &HttpOptions::default(),
+        ))
+        .unwrap();
+
+        assert_eq!(1..=21, source.zoom_range());
+    }
+}
@@ -0,0 +1,170 @@
+//! Persistent, on-disk storage of downloaded tiles.
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{freshness::Freshness, io::in_background};
+
+/// Directory where raw tile images are kept between application runs. Entries are keyed by the
+/// tile's URL, which identifies both the source and the [`crate::mercator::TileId`]. Files are
+/// accessed on worker threads, so the IO thread is not blocked.
+#[derive(Clone)]
+pub(crate) struct DiskCache {
+    root: Arc<Path>,
+}
+
+impl DiskCache {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = format!("{:016x}", fnv1a(key.as_bytes()));
+
+        // Spread files across subdirectories, so that none of them grows too large.
+        self.root.join(&hash[..2]).join(hash)
+    }
+
+    /// Get the tile's image along with its freshness, if it was stored before.
+    pub async fn get(&self, key: &str) -> Option<(Vec<u8>, Freshness)> {
+        let (cache, key) = (self.clone(), key.to_owned());
+        in_background(move || cache.read(&key)).await
+    }
+
+    /// Store the tile's image.
+    pub async fn put<D>(&self, key: &str, data: D, freshness: &Freshness) -> std::io::Result<()>
+    where
+        D: AsRef<[u8]> + Send + 'static,
+    {
+        let (cache, key, freshness) = (self.clone(), key.to_owned(), freshness.to_owned());
+        in_background(move || cache.write(&key, data.as_ref(), &freshness)).await
+    }
+
+    /// Update the freshness of already stored tile, e.g. after it was revalidated.
+    pub async fn put_freshness(&self, key: &str, freshness: &Freshness) -> std::io::Result<()> {
+        let (cache, key, freshness) = (self.clone(), key.to_owned(), freshness.to_owned());
+        in_background(move || cache.write_freshness(&key, &freshness)).await
+    }
+
+    fn read(&self, key: &str) -> Option<(Vec<u8>, Freshness)> {
+        let path = self.path(key);
+        let data = std::fs::read(&path).ok()?;
+
+        // Missing metadata is not an error, tile simply never expires then.
+        let freshness = std::fs::read_to_string(path.with_extension("meta"))
+            .map(|serialized| Freshness::deserialize(&serialized))
+            .unwrap_or_default();
+
+        Some((data, freshness))
+    }
+
+    fn write(&self, key: &str, data: &[u8], freshness: &Freshness) -> std::io::Result<()> {
+        let path = self.path(key);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        write_atomically(&path, data)?;
+        self.write_freshness(key, freshness)
+    }
+
+    fn write_freshness(&self, key: &str, freshness: &Freshness) -> std::io::Result<()> {
+        write_atomically(
+            &self.path(key).with_extension("meta"),
+            freshness.serialize().as_bytes(),
+        )
+    }
+}
+
+/// Write to a temporary file first, so that a crash can not leave a truncated file behind. Each
+/// write gets its own temporary file, as the same tile might be stored by several threads, or
+/// applications sharing the directory, at once.
+fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = std::fs::write(&temporary, data).and_then(|_| std::fs::rename(&temporary, path));
+    if result.is_err() {
+        // Do not leave the temporary file behind, it would never be cleaned up.
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// [FNV-1a](https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function) hash.
+/// Unlike `std`'s `DefaultHasher`, it is guaranteed to be stable across Rust releases, which is
+/// needed for file names.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_data_can_be_read_back() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(directory.path().to_owned());
+
+        assert_eq!(None, cache.read("https://example.com/1/2/3.png"));
+
+        let freshness = Freshness {
+            etag: Some("\"abc\"".to_owned()),
+            ..Default::default()
+        };
+
+        cache
+            .write("https://example.com/1/2/3.png", b"image", &freshness)
+            .unwrap();
+        assert_eq!(
+            Some((b"image".to_vec(), freshness)),
+            cache.read("https://example.com/1/2/3.png")
+        );
+
+        // Different key, different entry.
+        assert_eq!(None, cache.read("https://example.com/1/2/4.png"));
+    }
+
+    #[test]
+    fn concurrent_writes_of_the_same_tile_do_not_collide() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(directory.path().to_owned());
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        cache
+                            .write(
+                                "https://example.com/1/2/3.png",
+                                b"image",
+                                &Freshness::default(),
+                            )
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            Some(b"image".to_vec()),
+            cache
+                .read("https://example.com/1/2/3.png")
+                .map(|(data, _)| data)
+        );
+    }
+}
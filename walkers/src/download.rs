@@ -1,34 +1,319 @@
-use egui::Context;
-use futures::{SinkExt, StreamExt};
+use std::{
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
 use image::ImageError;
-use reqwest::header::USER_AGENT;
+use reqwest::{
+    header::{HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, USER_AGENT},
+    RequestBuilder, StatusCode,
+};
+
+use crate::{
+    disk_cache::DiskCache,
+    fetcher::{BoxFuture, Failure, Fetch, FetchError, Fetched, Fetcher, TileData},
+    freshness::{self, Freshness},
+    io::Downloader,
+    mercator::TileId,
+    providers::{Attribution, TileSource},
+    tiles::decode_in_background,
+};
+
+/// Controls how [`crate::Tiles`] obtains its images.
+#[derive(Default, Clone)]
+pub struct HttpOptions {
+    /// Directory where downloaded tiles are stored, so they do not need to be downloaded again
+    /// after the application restarts. Several [`crate::Tiles`] can share the same directory.
+    /// `None` disables the disk cache.
+    pub cache: Option<PathBuf>,
+
+    /// How to retry downloads which failed due to transient errors.
+    pub retry: RetryPolicy,
+
+    /// IO thread and HTTP client shared with other [`crate::Tiles`]. `None` starts a new thread
+    /// for these tiles only.
+    pub downloader: Option<Downloader>,
+
+    /// Credentials added to every tile request, e.g. [`BasicAuth`] or a token which needs to be
+    /// refreshed from time to time.
+    pub credentials: Option<Arc<dyn Credentials>>,
+}
+
+impl HttpOptions {
+    /// Client of the [`HttpOptions::downloader`], so that connections are reused.
+    pub(crate) fn client(&self) -> reqwest::Client {
+        self.downloader
+            .as_ref()
+            .map_or_else(reqwest::Client::new, |downloader| {
+                downloader.client().to_owned()
+            })
+    }
+}
+
+/// Adds credentials to tile requests, see [`HttpOptions::credentials`]. Static ones, such as API
+/// keys, can be simply returned by [`TileSource::headers`] instead.
+pub trait Credentials: Send + Sync + 'static {
+    /// Called for every tile request, e.g. to add a token with
+    /// [`reqwest::RequestBuilder::bearer_auth`]. If the token is about to expire, this is a good
+    /// place to refresh it.
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+    ) -> BoxFuture<'_, Result<RequestBuilder, FetchError>>;
+
+    /// Server rejected the credentials with `401 Unauthorized`. The tile is retried according to
+    /// the [`RetryPolicy`], so the next [`Credentials::authorize`] should use fresh ones.
+    fn rejected(&self) {}
+}
+
+/// HTTP basic authentication.
+#[derive(Debug, Clone)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+impl Credentials for BasicAuth {
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+    ) -> BoxFuture<'_, Result<RequestBuilder, FetchError>> {
+        let request = request.basic_auth(&self.username, self.password.as_ref());
+        Box::pin(futures::future::ready(Ok(request)))
+    }
+}
 
-use crate::{mercator::TileId, providers::TileSource, tiles::Texture};
+/// Failed downloads are retried with exponential backoff, but only if the error is transient,
+/// such as a timeout, a connection problem or a 5xx response. Permanent errors, like 404 or
+/// an image which can not be decoded, leave the tile empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries of a single tile. Zero disables retrying.
+    pub max_retries: u32,
+
+    /// Delay before the first retry. Each subsequent one is twice as long.
+    pub initial_delay: Duration,
+
+    /// Upper limit of the delay.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the next retry, or `None` if the tile should not be retried anymore.
+    pub(crate) fn delay(&self, failures: u32) -> Option<Duration> {
+        if failures == 0 || failures > self.max_retries {
+            return None;
+        }
+
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(failures - 1));
+        Some(delay.min(self.max_delay))
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
-enum Error {
+pub(crate) enum Error {
     #[error(transparent)]
     Http(reqwest::Error),
 
     #[error(transparent)]
     Image(ImageError),
+
+    #[error(transparent)]
+    Io(std::io::Error),
+
+    #[error(transparent)]
+    Credentials(FetchError),
+
+    #[error("credentials were rejected: {0}")]
+    Rejected(reqwest::Error),
 }
 
-/// Download and decode the tile.
-async fn download_and_decode(
+impl Error {
+    fn failure(&self) -> Failure {
+        match self {
+            Error::Http(e) => http_failure(e),
+            Error::Credentials(FetchError::Transient(_)) | Error::Rejected(_) => Failure::Transient,
+            _ => Failure::Permanent,
+        }
+    }
+}
+
+/// Request of the tile, with the headers given by its [`TileSource`].
+pub(crate) fn tile_request(
     client: &reqwest::Client,
     url: &str,
-    egui_ctx: &Context,
-) -> Result<Texture, Error> {
-    let image = client
+    headers: HeaderMap,
+) -> RequestBuilder {
+    // Headers given by the source replace the default ones.
+    client
         .get(url)
         .header(USER_AGENT, "Walkers")
-        .send()
+        .headers(headers)
+}
+
+/// Add the [`HttpOptions::credentials`] to the request, if there are any.
+pub(crate) async fn authorize(
+    request: RequestBuilder,
+    credentials: Option<&Arc<dyn Credentials>>,
+) -> Result<RequestBuilder, FetchError> {
+    match credentials {
+        Some(credentials) => credentials.authorize(request).await,
+        None => Ok(request),
+    }
+}
+
+/// Let the credentials know that the server rejected them, so fresh ones are used next time.
+pub(crate) fn report_rejected(status: StatusCode, credentials: Option<&Arc<dyn Credentials>>) {
+    if let (StatusCode::UNAUTHORIZED, Some(credentials)) = (status, credentials) {
+        credentials.rejected();
+    }
+}
+
+/// Download a document describing the tiles, such as TileJSON, with the client and credentials
+/// given by the options. Errors are converted with `http_error` and `credentials_error`, so each
+/// kind of document can report them in its own error type.
+#[cfg(any(feature = "bing", feature = "tilejson", feature = "wmts"))]
+pub(crate) async fn download_document<E>(
+    url: &str,
+    http_options: &HttpOptions,
+    http_error: impl Fn(reqwest::Error) -> E,
+    credentials_error: impl FnOnce(FetchError) -> E,
+) -> Result<String, E> {
+    let credentials = http_options.credentials.as_ref();
+    let request = tile_request(&http_options.client(), url, HeaderMap::new());
+    let request = authorize(request, credentials)
         .await
-        .map_err(Error::Http)?;
+        .map_err(credentials_error)?;
+
+    let response = request.send().await.map_err(&http_error)?;
+    report_rejected(response.status(), credentials);
+
+    response
+        .error_for_status()
+        .map_err(&http_error)?
+        .text()
+        .await
+        .map_err(http_error)
+}
+
+/// Check whether the HTTP request might succeed if tried again later.
+pub(crate) fn http_failure(e: &reqwest::Error) -> Failure {
+    if e.is_timeout()
+        || e.is_connect()
+        || e.is_request()
+        || e.is_body()
+        || e.status().is_some_and(|status| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        })
+    {
+        Failure::Transient
+    } else {
+        Failure::Permanent
+    }
+}
+
+/// Download and decode the tile, unless it can be found in the disk cache. If `stale` is given,
+/// the tile is already known, and only needs to be revalidated.
+async fn download_and_decode(
+    mut request: RequestBuilder,
+    url: &str,
+    stale: Option<Freshness>,
+    cache: Option<&DiskCache>,
+) -> Result<Fetched, Error> {
+    let now = freshness::now();
+    let cached = match cache {
+        Some(cache) => cache.get(url).await,
+        None => None,
+    };
+
+    let cached = match cached {
+        Some((image, freshness)) if !freshness.is_stale(now) => {
+            log::trace!("Found '{}' in the disk cache.", url);
+            match decode_in_background(image).await {
+                Ok(image) => return Ok(Fetched::Tile(image, freshness)),
+                Err(e) => {
+                    // Its validators are useless too, as the server would confirm the garbage.
+                    log::warn!("Cached '{}' is corrupted, downloading again: {}", url, e);
+                    None
+                }
+            }
+        }
+        cached => cached,
+    };
+
+    let validators = stale
+        .clone()
+        .or_else(|| cached.as_ref().map(|(_, freshness)| freshness.to_owned()));
+
+    if let Some(validators) = &validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let image = match request.send().await {
+        Ok(image) => image,
+        Err(e) => {
+            // Server can not be reached, e.g. because the device is offline. Expired tile from
+            // the disk cache is still better than nothing.
+            if let (None, Some((image, freshness))) = (&stale, cached) {
+                log::warn!(
+                    "Could not revalidate '{}', using the expired one: {}",
+                    url,
+                    e
+                );
+                let image = decode_in_background(image).await.map_err(Error::Image)?;
+                return Ok(Fetched::Tile(image, freshness));
+            }
+            return Err(Error::Http(e));
+        }
+    };
 
     log::debug!("Downloaded {:?}.", image.status());
 
+    if image.status() == StatusCode::NOT_MODIFIED {
+        if let Some(validators) = validators {
+            let freshness = validators.revalidated(image.headers(), now);
+
+            if let (Some(cache), Some(_)) = (cache, &cached) {
+                if let Err(e) = cache.put_freshness(url, &freshness).await {
+                    log::warn!("Could not update '{}' in the disk cache: {}", url, e);
+                }
+            }
+
+            // If the tile is not known yet, it must have been revalidated from the disk cache.
+            match (stale, cached) {
+                (Some(_), _) => return Ok(Fetched::NotModified(freshness)),
+                (None, Some((image, _))) => {
+                    let image = decode_in_background(image).await.map_err(Error::Image)?;
+                    return Ok(Fetched::Tile(image, freshness));
+                }
+                (None, None) => {}
+            }
+        }
+    }
+
+    let freshness = Freshness::from_headers(image.headers(), now);
+    let no_store = freshness::no_store(image.headers());
+
     let image = image
         .error_for_status()
         .map_err(Error::Http)?
@@ -36,52 +321,206 @@ async fn download_and_decode(
         .await
         .map_err(Error::Http)?;
 
-    Texture::new(&image, egui_ctx).map_err(Error::Image)
+    // `Bytes` are reference counted, so cloning them is cheap.
+    let decoded = decode_in_background(image.clone())
+        .await
+        .map_err(Error::Image)?;
+
+    // Only store images which could be decoded.
+    if let (Some(cache), false) = (cache, no_store) {
+        if let Err(e) = cache.put(url, image, &freshness).await {
+            log::warn!("Could not store '{}' in the disk cache: {}", url, e);
+        }
+    }
+
+    Ok(Fetched::Tile(decoded, freshness))
+}
+
+/// What [`download_to_disk`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stored {
+    Downloaded { bytes: usize },
+    AlreadyCached,
 }
 
-async fn download_continuously_impl<S>(
-    source: S,
-    mut request_rx: futures::channel::mpsc::Receiver<TileId>,
-    mut tile_tx: futures::channel::mpsc::Sender<(TileId, Texture)>,
-    egui_ctx: Context,
-) -> Result<(), ()>
+/// Download the tile straight to the disk cache, without decoding it, unless a fresh copy is
+/// already there. Request is authorized only if it is actually sent.
+pub(crate) async fn download_to_disk(
+    request: RequestBuilder,
+    url: &str,
+    cache: &DiskCache,
+    credentials: Option<&Arc<dyn Credentials>>,
+) -> Result<Stored, Error> {
+    let now = freshness::now();
+
+    if let Some((_, freshness)) = cache.get(url).await {
+        if !freshness.is_stale(now) {
+            return Ok(Stored::AlreadyCached);
+        }
+    }
+
+    let request = authorize(request, credentials)
+        .await
+        .map_err(Error::Credentials)?;
+    let response = request.send().await.map_err(Error::Http)?;
+    report_rejected(response.status(), credentials);
+    let response = response.error_for_status().map_err(Error::Http)?;
+
+    // `no-store` is not honored here, as storing the tiles is the whole point.
+    let freshness = Freshness::from_headers(response.headers(), now);
+    let image = response.bytes().await.map_err(Error::Http)?;
+
+    // Do not store garbage, such as HTML error pages.
+    image::guess_format(&image).map_err(Error::Image)?;
+
+    let bytes = image.len();
+    cache.put(url, image, &freshness).await.map_err(Error::Io)?;
+    Ok(Stored::Downloaded { bytes })
+}
+
+/// [`Fetcher`] downloading tiles from the URLs given by the [`TileSource`], with all the
+/// features of [`HttpOptions`]. This is what [`crate::Tiles::new`] uses.
+pub struct HttpFetcher<S> {
+    // Source does not need to be `Sync`, while the fetcher is shared between downloads.
+    source: Mutex<S>,
+
+    // Keep it here to reuse connections as much as possible.
+    client: reqwest::Client,
+
+    cache: Option<DiskCache>,
+
+    credentials: Option<Arc<dyn Credentials>>,
+}
+
+impl<S> HttpFetcher<S>
 where
     S: TileSource + Send + 'static,
 {
-    // Keep outside the loop to reuse it as much as possible.
-    let client = reqwest::Client::new();
+    pub fn new(source: S, http_options: HttpOptions) -> Self {
+        Self {
+            source: Mutex::new(source),
+            client: http_options.client(),
+            cache: http_options.cache.map(DiskCache::new),
+            credentials: http_options.credentials,
+        }
+    }
 
-    loop {
-        let request = request_rx.next().await.ok_or(())?;
-        let url = source.tile_url(request);
+    fn source(&self) -> MutexGuard<'_, S> {
+        // Source is always consistent, even if some thread panicked while holding the lock.
+        self.source
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    async fn download(&self, url: String, stale: Option<Freshness>) -> Result<Fetched, Error> {
+        log::debug!("Downloading {}.", url);
+
+        let request = tile_request(&self.client, &url, self.source().headers());
+        let result = match authorize(request, self.credentials.as_ref()).await {
+            Ok(request) => download_and_decode(request, &url, stale, self.cache.as_ref()).await,
+            Err(e) => Err(Error::Credentials(e)),
+        };
 
-        log::debug!("Getting {:?} from {}.", request, url);
+        result.map_err(|e| {
+            log::warn!("Could not download '{}': {}", &url, e);
 
-        match download_and_decode(&client, &url, &egui_ctx).await {
-            Ok(tile) => {
-                tile_tx.send((request, tile)).await.map_err(|_| ())?;
-                egui_ctx.request_repaint();
+            match (e, &self.credentials) {
+                (Error::Http(e), Some(credentials))
+                    if e.status() == Some(StatusCode::UNAUTHORIZED) =>
+                {
+                    credentials.rejected();
+                    Error::Rejected(e)
+                }
+                (e, _) => e,
             }
-            Err(e) => {
-                log::warn!("Could not download '{}': {}", &url, e);
+        })
+    }
+}
+
+impl<S> Fetcher for HttpFetcher<S>
+where
+    S: TileSource + Send + 'static,
+{
+    fn fetch(&self, tile_id: TileId) -> BoxFuture<'_, Result<TileData, FetchError>> {
+        let url = self.source().tile_url(tile_id);
+
+        Box::pin(async move {
+            match self.download(url, None).await {
+                Ok(Fetched::Tile(image, _)) => Ok(TileData::Image(image)),
+                Ok(Fetched::NotModified(_)) => Err(FetchError::Permanent(
+                    "tile was not modified, but it was not known before".into(),
+                )),
+                Err(e) => Err(match e.failure() {
+                    Failure::Transient => FetchError::Transient(e.into()),
+                    Failure::Permanent => FetchError::Permanent(e.into()),
+                }),
             }
-        }
+        })
+    }
+
+    fn attribution(&self) -> Attribution {
+        self.source().attribution()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.source().tile_size()
+    }
+
+    fn max_concurrent_fetches(&self) -> usize {
+        self.source().max_concurrent_downloads()
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.source().zoom_range()
     }
 }
 
-/// Continuously download tiles requested via request channel.
-pub(crate) async fn download_continuously<S>(
-    source: S,
-    request_rx: futures::channel::mpsc::Receiver<TileId>,
-    tile_tx: futures::channel::mpsc::Sender<(TileId, Texture)>,
-    egui_ctx: Context,
-) where
+impl<S> Fetch for HttpFetcher<S>
+where
     S: TileSource + Send + 'static,
 {
-    if download_continuously_impl(source, request_rx, tile_tx, egui_ctx)
-        .await
-        .is_err()
-    {
-        log::error!("Error from IO runtime.");
+    fn fetch(
+        &self,
+        tile_id: TileId,
+        stale: Option<Freshness>,
+    ) -> BoxFuture<'_, Result<Fetched, Failure>> {
+        let url = self.source().tile_url(tile_id);
+        Box::pin(async move { self.download(url, stale).await.map_err(|e| e.failure()) })
+    }
+
+    fn attribution(&self) -> Attribution {
+        Fetcher::attribution(self)
+    }
+
+    fn tile_size(&self) -> u32 {
+        Fetcher::tile_size(self)
+    }
+
+    fn max_concurrent_fetches(&self) -> usize {
+        Fetcher::max_concurrent_fetches(self)
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        Fetcher::zoom_range(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        let policy = RetryPolicy {
+            max_retries: 4,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+
+        assert_eq!(Some(Duration::from_secs(1)), policy.delay(1));
+        assert_eq!(Some(Duration::from_secs(2)), policy.delay(2));
+        assert_eq!(Some(Duration::from_secs(4)), policy.delay(3));
+        assert_eq!(Some(Duration::from_secs(5)), policy.delay(4));
+        assert_eq!(None, policy.delay(5));
     }
 }
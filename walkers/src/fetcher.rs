@@ -0,0 +1,345 @@
+//! Obtaining tile images from any kind of source, not only HTTP servers.
+use std::{
+    ops::RangeInclusive,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use egui::{ColorImage, Context};
+use futures::{SinkExt, Stream, StreamExt, TryStreamExt};
+
+use crate::{
+    freshness::Freshness, io::in_background, mercator::TileId, providers::Attribution,
+    tiles::decode_in_background,
+};
+
+/// Future returned by the [`Fetcher`]. It has to be `Send`, except in WASM, where everything
+/// runs on a single thread.
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxFuture<'a, T> = futures::future::BoxFuture<'a, T>;
+
+/// Future returned by the [`Fetcher`]. It has to be `Send`, except in WASM, where everything
+/// runs on a single thread.
+#[cfg(target_arch = "wasm32")]
+pub type BoxFuture<'a, T> = futures::future::LocalBoxFuture<'a, T>;
+
+/// Image of a tile, as returned by the [`Fetcher`].
+pub enum TileData {
+    /// Encoded image, such as PNG or JPEG. It gets decoded on a worker thread.
+    Bytes(Vec<u8>),
+
+    /// Already decoded image, e.g. a procedurally generated one.
+    Image(ColorImage),
+}
+
+/// Reason why the [`Fetcher`] could not obtain the tile.
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    /// It might succeed if tried again later, like a timeout. Such tiles are retried with
+    /// the default [`crate::RetryPolicy`].
+    #[error(transparent)]
+    Transient(Box<dyn std::error::Error + Send + Sync>),
+
+    /// There is no point in trying again, e.g. because the source does not have this tile.
+    /// Such tile stays empty.
+    #[error(transparent)]
+    Permanent(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Source of tile images for [`crate::Tiles::with_fetcher`]. Unlike
+/// [`crate::providers::TileSource`], it is not limited to HTTP URLs - tiles can be read from
+/// local files, databases, generated on the fly, or downloaded using a custom transport.
+///
+/// # Examples
+///
+/// ```
+/// use walkers::{mercator::TileId, providers::Attribution};
+/// use walkers::{BoxFuture, FetchError, Fetcher, TileData};
+///
+/// struct Checkerboard;
+///
+/// impl Fetcher for Checkerboard {
+///     fn fetch(&self, tile_id: TileId) -> BoxFuture<'_, Result<TileData, FetchError>> {
+///         Box::pin(async move {
+///             let color = if (tile_id.x + tile_id.y) % 2 == 0 {
+///                 egui::Color32::WHITE
+///             } else {
+///                 egui::Color32::GRAY
+///             };
+///             Ok(TileData::Image(egui::ColorImage::new([256, 256], color)))
+///         })
+///     }
+///
+///     fn attribution(&self) -> Attribution {
+///         Attribution::default()
+///     }
+/// }
+/// ```
+pub trait Fetcher: Send + Sync + 'static {
+    /// Obtain the tile. It is called from the IO thread, for as many tiles at once as
+    /// [`Fetcher::max_concurrent_fetches`] allows.
+    fn fetch(&self, tile_id: TileId) -> BoxFuture<'_, Result<TileData, FetchError>>;
+
+    fn attribution(&self) -> Attribution;
+
+    /// Size of each tile, should be a multiple of 256
+    fn tile_size(&self) -> u32 {
+        256
+    }
+
+    /// Maximum number of tiles being fetched at the same time.
+    fn max_concurrent_fetches(&self) -> usize {
+        6
+    }
+
+    /// Zoom levels for which the fetcher has tiles. Beyond the maximum, the most detailed tiles
+    /// get stretched.
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        0..=19
+    }
+}
+
+/// Reason why the tile could not be obtained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Failure {
+    /// It might succeed if tried again later.
+    Transient,
+
+    /// There is no point in trying again.
+    Permanent,
+}
+
+/// Tile obtained by the IO thread. Its image becomes a texture in the UI thread.
+pub(crate) enum Fetched {
+    /// New image of the tile.
+    Tile(ColorImage, Freshness),
+
+    /// Tile did not change since it was obtained, but it stays valid for longer now.
+    NotModified(Freshness),
+}
+
+/// What the IO thread actually uses. Besides all [`Fetcher`]s, it is implemented by
+/// [`crate::HttpFetcher`] directly, as it needs to know about expired tiles to revalidate them.
+pub(crate) trait Fetch: Send + Sync + 'static {
+    /// Obtain the tile. If `stale` is given, the tile is already known, and only needs to be
+    /// revalidated.
+    fn fetch(
+        &self,
+        tile_id: TileId,
+        stale: Option<Freshness>,
+    ) -> BoxFuture<'_, Result<Fetched, Failure>>;
+
+    fn attribution(&self) -> Attribution;
+
+    fn tile_size(&self) -> u32;
+
+    fn max_concurrent_fetches(&self) -> usize;
+
+    fn zoom_range(&self) -> RangeInclusive<u8>;
+}
+
+/// Adapts a [`Fetcher`] for the IO thread. Tiles obtained this way never expire.
+pub(crate) struct Decoding<F>(pub F);
+
+impl<F: Fetcher> Fetch for Decoding<F> {
+    fn fetch(
+        &self,
+        tile_id: TileId,
+        _stale: Option<Freshness>,
+    ) -> BoxFuture<'_, Result<Fetched, Failure>> {
+        Box::pin(async move {
+            let image = match self.0.fetch(tile_id).await {
+                Ok(TileData::Bytes(bytes)) => decode_in_background(bytes).await.map_err(|e| {
+                    log::warn!("Could not decode {:?}: {}", tile_id, e);
+                    Failure::Permanent
+                })?,
+                Ok(TileData::Image(image)) => image,
+                Err(e) => {
+                    log::warn!("Could not fetch {:?}: {}", tile_id, e);
+                    return Err(match e {
+                        FetchError::Transient(_) => Failure::Transient,
+                        FetchError::Permanent(_) => Failure::Permanent,
+                    });
+                }
+            };
+
+            Ok(Fetched::Tile(image, Freshness::default()))
+        })
+    }
+
+    fn attribution(&self) -> Attribution {
+        self.0.attribution()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.0.tile_size()
+    }
+
+    fn max_concurrent_fetches(&self) -> usize {
+        self.0.max_concurrent_fetches()
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.0.zoom_range()
+    }
+}
+
+/// Transformation of decoded tile images, see [`crate::Tiles::set_transform`].
+pub(crate) type Transform = Arc<dyn Fn(&mut ColorImage) + Send + Sync>;
+
+/// [`Transform`] shared between [`crate::Tiles`] and the IO thread. Each change increments its
+/// generation, so tiles transformed the previous way can be recognized.
+#[derive(Clone, Default)]
+pub(crate) struct SharedTransform(Arc<Mutex<(u64, Option<Transform>)>>);
+
+impl SharedTransform {
+    pub(crate) fn set(&self, transform: Option<Transform>) {
+        let mut shared = self.lock();
+        shared.0 += 1;
+        shared.1 = transform;
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.lock().0
+    }
+
+    fn get(&self) -> (u64, Option<Transform>) {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, (u64, Option<Transform>)> {
+        // Transform is always consistent, even if some thread panicked while holding the lock.
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Fetched tile, along with the generation of [`SharedTransform`] which was applied to it.
+pub(crate) type FetchedTile = (TileId, u64, Result<Fetched, Failure>);
+
+async fn fetch_continuously_impl<F, R>(
+    fetcher: F,
+    requests: R,
+    transform: SharedTransform,
+    tile_tx: futures::channel::mpsc::Sender<FetchedTile>,
+    egui_ctx: Context,
+) -> Result<(), ()>
+where
+    F: Fetch,
+    R: Stream<Item = (TileId, Option<Freshness>)>,
+{
+    let (fetcher, transform, egui_ctx) = (&fetcher, &transform, &egui_ctx);
+    let max_concurrent_fetches = fetcher.max_concurrent_fetches();
+
+    requests
+        .map(Ok)
+        .try_for_each_concurrent(max_concurrent_fetches, move |(request, stale)| {
+            let mut tile_tx = tile_tx.clone();
+
+            async move {
+                log::debug!("Fetching {:?}.", request);
+                let (generation, transform) = transform.get();
+                let result = match (fetcher.fetch(request, stale).await, transform) {
+                    (Ok(Fetched::Tile(mut image, freshness)), Some(transform)) => {
+                        let image = in_background(move || {
+                            transform(&mut image);
+                            image
+                        })
+                        .await;
+                        Ok(Fetched::Tile(image, freshness))
+                    }
+                    (result, _) => result,
+                };
+
+                tile_tx
+                    .send((request, generation, result))
+                    .await
+                    .map_err(|_| ())?;
+                egui_ctx.request_repaint();
+                Ok(())
+            }
+        })
+        .await
+}
+
+/// Continuously fetch tiles requested via the stream.
+pub(crate) async fn fetch_continuously<F, R>(
+    fetcher: F,
+    requests: R,
+    transform: SharedTransform,
+    tile_tx: futures::channel::mpsc::Sender<FetchedTile>,
+    egui_ctx: Context,
+) where
+    F: Fetch,
+    R: Stream<Item = (TileId, Option<Freshness>)>,
+{
+    if fetch_continuously_impl(fetcher, requests, transform, tile_tx, egui_ctx)
+        .await
+        .is_err()
+    {
+        log::error!("Error from IO runtime.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::{queue::Priority, Downloader, Tiles};
+
+    static TILE_ID: TileId = TileId {
+        x: 1,
+        y: 2,
+        zoom: 3,
+    };
+
+    /// Fails transiently a few times, then generates a plain tile.
+    struct Flaky {
+        failures_left: AtomicU32,
+    }
+
+    impl Fetcher for Flaky {
+        fn fetch(&self, _: TileId) -> BoxFuture<'_, Result<TileData, FetchError>> {
+            Box::pin(async move {
+                if self.failures_left.load(Ordering::Relaxed) > 0 {
+                    self.failures_left.fetch_sub(1, Ordering::Relaxed);
+                    Err(FetchError::Transient("not yet".into()))
+                } else {
+                    Ok(TileData::Image(ColorImage::new(
+                        [256, 256],
+                        egui::Color32::WHITE,
+                    )))
+                }
+            })
+        }
+
+        fn attribution(&self) -> Attribution {
+            Attribution::default()
+        }
+    }
+
+    #[test]
+    fn tiles_from_custom_fetcher() {
+        let _ = env_logger::try_init();
+
+        let fetcher = Flaky {
+            failures_left: AtomicU32::new(0),
+        };
+        let mut tiles = Tiles::with_fetcher(fetcher, &Downloader::new(), Context::default());
+        while tiles.at(TILE_ID, Priority::default()).is_none() {}
+    }
+
+    #[test]
+    fn transient_fetch_errors_are_retried() {
+        let _ = env_logger::try_init();
+
+        let fetcher = Flaky {
+            failures_left: AtomicU32::new(1),
+        };
+        let mut tiles = Tiles::with_fetcher(fetcher, &Downloader::new(), Context::default());
+
+        // Default retry policy waits one second before the first retry.
+        while tiles.at(TILE_ID, Priority::default()).is_none() {}
+    }
+}
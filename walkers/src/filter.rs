@@ -0,0 +1,209 @@
+//! Color filters applied to tiles when they are decoded, see [`crate::Tiles::set_filters`].
+use egui::{Color32, ColorImage};
+
+/// Color transformation of a tile. Formulas follow these of CSS filters, operating on
+/// gamma-encoded colors. Alpha is left untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorFilter {
+    /// Turn light colors into dark ones, but keep the hues, so water stays blue and parks stay
+    /// green. Same as `invert(1) hue-rotate(180deg)` in CSS.
+    DarkMode,
+
+    /// Negative of the image.
+    Invert,
+
+    /// Rotate hues by the given angle, in degrees.
+    HueRotate(f32),
+
+    /// Shades of gray only.
+    Grayscale,
+
+    /// Remove the given fraction of saturation. `0.` leaves the image unchanged, `1.` is the
+    /// same as [`ColorFilter::Grayscale`].
+    Desaturate(f32),
+
+    /// Multiply each pixel by the color.
+    Tint(Color32),
+
+    /// Values above `1.` brighten the mid-tones, values below darken them. Black and white
+    /// stay the same.
+    Gamma(f32),
+
+    /// Multiply all channels by the factor. `1.` leaves the image unchanged.
+    Brightness(f32),
+
+    /// Push colors away from the middle gray by the factor, or towards it if the factor is
+    /// below `1.`.
+    Contrast(f32),
+}
+
+/// Coefficients of luminance, as used by CSS filters.
+const LUMINANCE: [f32; 3] = [0.213, 0.715, 0.072];
+
+impl ColorFilter {
+    fn apply_to_rgb(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let rgb = match *self {
+            Self::DarkMode => Self::HueRotate(180.).apply_to_rgb(Self::Invert.apply_to_rgb(rgb)),
+            Self::Invert => rgb.map(|c| 1. - c),
+            Self::HueRotate(degrees) => {
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                let [lr, lg, lb] = LUMINANCE;
+                multiply(
+                    [
+                        [
+                            lr + cos * (1. - lr) - sin * lr,
+                            lg - cos * lg - sin * lg,
+                            lb - cos * lb + sin * (1. - lb),
+                        ],
+                        [
+                            lr - cos * lr + sin * 0.143,
+                            lg + cos * (1. - lg) + sin * 0.140,
+                            lb - cos * lb - sin * 0.283,
+                        ],
+                        [
+                            lr - cos * lr - sin * (1. - lr),
+                            lg - cos * lg + sin * lg,
+                            lb + cos * (1. - lb) + sin * lb,
+                        ],
+                    ],
+                    rgb,
+                )
+            }
+            Self::Grayscale => Self::Desaturate(1.).apply_to_rgb(rgb),
+            Self::Desaturate(amount) => {
+                let saturation = 1. - amount.clamp(0., 1.);
+                let [lr, lg, lb] = LUMINANCE;
+                multiply(
+                    [
+                        [
+                            lr + (1. - lr) * saturation,
+                            lg - lg * saturation,
+                            lb - lb * saturation,
+                        ],
+                        [
+                            lr - lr * saturation,
+                            lg + (1. - lg) * saturation,
+                            lb - lb * saturation,
+                        ],
+                        [
+                            lr - lr * saturation,
+                            lg - lg * saturation,
+                            lb + (1. - lb) * saturation,
+                        ],
+                    ],
+                    rgb,
+                )
+            }
+            Self::Tint(color) => {
+                let [r, g, b, _] = color.to_srgba_unmultiplied();
+                let tint = [r, g, b].map(|c| c as f32 / 255.);
+                [rgb[0] * tint[0], rgb[1] * tint[1], rgb[2] * tint[2]]
+            }
+            Self::Gamma(gamma) => rgb.map(|c| c.powf(1. / gamma)),
+            Self::Brightness(factor) => rgb.map(|c| c * factor),
+            Self::Contrast(factor) => rgb.map(|c| (c - 0.5) * factor + 0.5),
+        };
+
+        // Like in CSS, each filter gets valid colors.
+        rgb.map(|c| c.clamp(0., 1.))
+    }
+}
+
+fn multiply(matrix: [[f32; 3]; 3], rgb: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2])
+}
+
+/// Apply the filters, in order, to each pixel of the image.
+pub(crate) fn apply(filters: &[ColorFilter], image: &mut ColorImage) {
+    for pixel in &mut image.pixels {
+        let [r, g, b, a] = pixel.to_srgba_unmultiplied();
+        let rgb = filters
+            .iter()
+            .fold([r, g, b].map(|c| c as f32 / 255.), |rgb, filter| {
+                filter.apply_to_rgb(rgb)
+            });
+        let [r, g, b] = rgb.map(|c| (c * 255.).round() as u8);
+        *pixel = Color32::from_rgba_unmultiplied(r, g, b, a);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered(filters: &[ColorFilter], color: Color32) -> Color32 {
+        let mut image = ColorImage::new([1, 1], color);
+        apply(filters, &mut image);
+        image.pixels[0]
+    }
+
+    #[test]
+    fn dark_mode_swaps_black_and_white_and_keeps_hues() {
+        let dark_mode = [ColorFilter::DarkMode];
+
+        assert_eq!(Color32::BLACK, filtered(&dark_mode, Color32::WHITE));
+        assert_eq!(Color32::WHITE, filtered(&dark_mode, Color32::BLACK));
+
+        // Light blue becomes dark blue.
+        let [r, g, b, _] = filtered(&dark_mode, Color32::from_rgb(170, 211, 223)).to_array();
+        assert!(b > r && b > g);
+        assert!(r < 128 && g < 128);
+    }
+
+    #[test]
+    fn grayscale_and_desaturate() {
+        let color = Color32::from_rgb(200, 100, 50);
+
+        let [r, g, b, _] = filtered(&[ColorFilter::Grayscale], color).to_array();
+        assert_eq!(r, g);
+        assert_eq!(g, b);
+
+        assert_eq!(
+            filtered(&[ColorFilter::Grayscale], color),
+            filtered(&[ColorFilter::Desaturate(1.)], color)
+        );
+        assert_eq!(color, filtered(&[ColorFilter::Desaturate(0.)], color));
+    }
+
+    #[test]
+    fn tint_gamma_brightness_and_contrast() {
+        let gray = Color32::from_gray(128);
+
+        assert_eq!(
+            Color32::from_rgb(128, 0, 0),
+            filtered(&[ColorFilter::Tint(Color32::RED)], gray)
+        );
+        assert!(filtered(&[ColorFilter::Gamma(2.)], gray).r() > 128);
+        assert_eq!(
+            Color32::WHITE,
+            filtered(&[ColorFilter::Gamma(2.)], Color32::WHITE)
+        );
+        assert_eq!(
+            Color32::from_gray(64),
+            filtered(&[ColorFilter::Brightness(0.5)], gray)
+        );
+        assert_eq!(
+            Color32::WHITE,
+            filtered(&[ColorFilter::Contrast(10.)], Color32::from_gray(200))
+        );
+    }
+
+    #[test]
+    fn filters_are_applied_in_order_and_alpha_is_kept() {
+        let color = Color32::from_rgba_unmultiplied(255, 255, 255, 128);
+
+        let [.., a] = filtered(&[ColorFilter::Invert], color).to_srgba_unmultiplied();
+        assert_eq!(128, a);
+
+        // Brightening black does nothing, but brightening the inverted one does.
+        let black = Color32::BLACK;
+        assert_eq!(
+            Color32::WHITE,
+            filtered(&[ColorFilter::Brightness(0.5), ColorFilter::Invert], black)
+        );
+        assert_eq!(
+            Color32::from_gray(128),
+            filtered(&[ColorFilter::Invert, ColorFilter::Brightness(0.5)], black)
+        );
+    }
+}
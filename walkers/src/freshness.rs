@@ -0,0 +1,230 @@
+//! HTTP caching rules (`Cache-Control`, `Expires`, `ETag`, `Last-Modified`) applied to tiles.
+//! <https://developer.mozilla.org/en-US/docs/Web/HTTP/Caching>
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, AGE, CACHE_CONTROL, ETAG, EXPIRES, LAST_MODIFIED};
+
+/// Tiles are never revalidated more often than this, even if the server asks for it. Otherwise,
+/// `no-cache` tiles would be revalidated in every frame.
+const MIN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// How long the tile stays valid, and how to check whether it changed afterwards.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Freshness {
+    /// Time since the Unix epoch, after which the tile should be revalidated. `None` means
+    /// that it never expires.
+    pub expires: Option<Duration>,
+
+    /// Value of the `ETag` header.
+    pub etag: Option<String>,
+
+    /// Value of the `Last-Modified` header.
+    pub last_modified: Option<String>,
+}
+
+impl Freshness {
+    /// Read the caching headers of a response.
+    pub fn from_headers(headers: &HeaderMap, now: Duration) -> Self {
+        Self {
+            expires: expires(headers, now),
+            etag: header(headers, ETAG),
+            last_modified: header(headers, LAST_MODIFIED),
+        }
+    }
+
+    /// Update after the server confirmed, with `304 Not Modified`, that the tile did not change.
+    pub fn revalidated(self, headers: &HeaderMap, now: Duration) -> Self {
+        let updated = Self::from_headers(headers, now);
+        Self {
+            expires: updated.expires,
+            etag: updated.etag.or(self.etag),
+            last_modified: updated.last_modified.or(self.last_modified),
+        }
+    }
+
+    pub fn is_stale(&self, now: Duration) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Time left until the tile expires, if it does at all.
+    pub fn lifetime(&self, now: Duration) -> Option<Duration> {
+        self.expires.map(|expires| expires.saturating_sub(now))
+    }
+
+    /// Serialize to a simple, line-based format.
+    pub fn serialize(&self) -> String {
+        let mut serialized = String::new();
+
+        if let Some(expires) = self.expires {
+            serialized += &format!("expires {}\n", expires.as_secs());
+        }
+
+        if let Some(etag) = &self.etag {
+            serialized += &format!("etag {}\n", etag);
+        }
+
+        if let Some(last_modified) = &self.last_modified {
+            serialized += &format!("last-modified {}\n", last_modified);
+        }
+
+        serialized
+    }
+
+    /// Inverse of [`Freshness::serialize`]. Unknown or malformed lines are ignored.
+    pub fn deserialize(serialized: &str) -> Self {
+        let mut freshness = Self::default();
+
+        for line in serialized.lines() {
+            match line.split_once(' ') {
+                Some(("expires", value)) => {
+                    freshness.expires = value.parse().ok().map(Duration::from_secs);
+                }
+                Some(("etag", value)) => freshness.etag = Some(value.to_owned()),
+                Some(("last-modified", value)) => freshness.last_modified = Some(value.to_owned()),
+                _ => log::debug!("Ignoring '{}'.", line),
+            }
+        }
+
+        freshness
+    }
+}
+
+/// Whether the server forbids storing the response.
+pub(crate) fn no_store(headers: &HeaderMap) -> bool {
+    cache_control(headers).any(|directive| directive == "no-store")
+}
+
+/// Current time since the Unix epoch.
+pub(crate) fn now() -> Duration {
+    web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers.get(name)?.to_str().ok().map(str::to_owned)
+}
+
+fn cache_control(headers: &HeaderMap) -> impl Iterator<Item = String> + '_ {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|directive| directive.trim().to_ascii_lowercase())
+}
+
+/// Calculate the expiration time. `Cache-Control` takes precedence over `Expires`.
+fn expires(headers: &HeaderMap, now: Duration) -> Option<Duration> {
+    let lifetime = cache_control(headers)
+        .find_map(|directive| {
+            if directive == "no-cache" {
+                Some(Duration::ZERO)
+            } else {
+                let max_age = directive.strip_prefix("max-age=")?;
+                max_age.parse().ok().map(Duration::from_secs)
+            }
+        })
+        .map(|max_age| {
+            // Response might have been waiting in some proxy's cache for a while.
+            let age = header(headers, AGE)
+                .and_then(|age| age.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or_default();
+            max_age.saturating_sub(age)
+        })
+        .or_else(|| {
+            let expires = httpdate::parse_http_date(&header(headers, EXPIRES)?).ok()?;
+            let expires = expires.duration_since(std::time::UNIX_EPOCH).ok()?;
+            Some(expires.saturating_sub(now))
+        })?;
+
+    Some(now + lifetime.max(MIN_LIFETIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const NOW: Duration = Duration::from_secs(1_700_000_000);
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    reqwest::header::HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn no_caching_headers_means_never_expiring() {
+        let freshness = Freshness::from_headers(&HeaderMap::new(), NOW);
+        assert_eq!(Freshness::default(), freshness);
+        assert!(!freshness.is_stale(NOW + Duration::from_secs(1_000_000)));
+    }
+
+    #[test]
+    fn max_age_takes_precedence_over_expires() {
+        let freshness = Freshness::from_headers(
+            &headers(&[
+                ("cache-control", "public, max-age=3600"),
+                ("expires", "Thu, 01 Jan 1970 00:00:00 GMT"),
+                ("age", "600"),
+                ("etag", "\"abc\""),
+            ]),
+            NOW,
+        );
+
+        assert_eq!(Some(NOW + Duration::from_secs(3000)), freshness.expires);
+        assert_eq!(Some("\"abc\"".to_owned()), freshness.etag);
+        assert!(!freshness.is_stale(NOW + Duration::from_secs(2999)));
+        assert!(freshness.is_stale(NOW + Duration::from_secs(3000)));
+    }
+
+    #[test]
+    fn expires_header() {
+        let freshness = Freshness::from_headers(
+            &headers(&[("expires", "Tue, 14 Nov 2023 23:13:20 GMT")]),
+            NOW,
+        );
+
+        assert_eq!(Some(NOW + Duration::from_secs(3600)), freshness.expires);
+    }
+
+    #[test]
+    fn no_cache_is_revalidated_after_minimum_lifetime() {
+        let freshness = Freshness::from_headers(&headers(&[("cache-control", "no-cache")]), NOW);
+        assert_eq!(Some(NOW + MIN_LIFETIME), freshness.expires);
+    }
+
+    #[test]
+    fn revalidation_keeps_validators_which_were_not_sent_again() {
+        let freshness = Freshness {
+            expires: Some(NOW),
+            etag: Some("\"abc\"".to_owned()),
+            last_modified: Some("Tue, 14 Nov 2023 22:13:20 GMT".to_owned()),
+        }
+        .revalidated(&headers(&[("cache-control", "max-age=600")]), NOW);
+
+        assert_eq!(Some(NOW + Duration::from_secs(600)), freshness.expires);
+        assert_eq!(Some("\"abc\"".to_owned()), freshness.etag);
+        assert!(freshness.last_modified.is_some());
+    }
+
+    #[test]
+    fn serialize_there_and_back() {
+        let freshness = Freshness {
+            expires: Some(NOW),
+            etag: Some("W/\"abc def\"".to_owned()),
+            last_modified: Some("Tue, 14 Nov 2023 22:13:20 GMT".to_owned()),
+        };
+
+        assert_eq!(freshness, Freshness::deserialize(&freshness.serialize()));
+        assert_eq!(Freshness::default(), Freshness::deserialize(""));
+    }
+}
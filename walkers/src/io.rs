@@ -1,25 +1,146 @@
 //! Managed thread for Tokio runtime.
-use std::future::Future;
+use std::{future::Future, sync::Arc};
+
+use futures::future::AbortHandle;
 
 #[cfg(not(target_arch = "wasm32"))]
-pub use native::TokioRuntimeThread as Runtime;
+use native::TokioRuntimeThread as Runtime;
 
 #[cfg(target_arch = "wasm32")]
-pub use web::WasmBindgenFutures as Runtime;
+use web::WasmBindgenFutures as Runtime;
+
+/// IO thread and HTTP client, which can be shared by many [`crate::Tiles`] to save threads and
+/// reuse connections, see [`crate::HttpOptions::downloader`]. Each [`crate::Tiles`] still has its
+/// own source and cache. Cloning it gives another handle to the same thread.
+#[derive(Clone)]
+pub struct Downloader(Arc<Shared>);
+
+struct Shared {
+    runtime: Runtime,
+
+    // Keep it here to reuse connections as much as possible.
+    client: reqwest::Client,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Downloader {
+    /// Start a new IO thread. It stops when all handles, and all [`crate::Tiles`] using it, are
+    /// dropped.
+    pub fn new() -> Self {
+        Self(Arc::new(Shared {
+            runtime: Runtime::new(),
+            client: reqwest::Client::new(),
+        }))
+    }
+
+    pub(crate) fn client(&self) -> &reqwest::Client {
+        &self.0.client
+    }
+
+    /// Run the future in the IO thread until it finishes, or the returned [`Task`] is dropped.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn spawn<F>(&self, f: F) -> Task
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (f, abort_handle) = futures::future::abortable(f);
+        self.0.runtime.spawn(async move {
+            let _ = f.await;
+        });
+        self.task(abort_handle)
+    }
+
+    /// Run the future in the IO thread until it finishes, or the returned [`Task`] is dropped.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn spawn<F>(&self, f: F) -> Task
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let (f, abort_handle) = futures::future::abortable(f);
+        self.0.runtime.spawn(async move {
+            let _ = f.await;
+        });
+        self.task(abort_handle)
+    }
+
+    fn task(&self, abort_handle: AbortHandle) -> Task {
+        Task {
+            abort_handle,
+            _downloader: self.clone(),
+        }
+    }
+}
+
+/// Run CPU-heavy work, such as decoding images, on a worker thread, so the IO thread is free to
+/// handle other downloads.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn in_background<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// There are no threads in WASM, so the work is simply done right away.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn in_background<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    f()
+}
+
+/// Run the future to completion, in a Tokio runtime which is needed by `reqwest` and
+/// [`in_background`].
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// Future running in the [`Downloader`]. Dropping it cancels the future.
+pub(crate) struct Task {
+    abort_handle: AbortHandle,
+
+    // Thread must outlive the future.
+    _downloader: Downloader,
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
 
 #[cfg(target_arch = "wasm32")]
 mod web {
     use super::*;
 
+    #[derive(Default)]
     pub struct WasmBindgenFutures;
 
     impl WasmBindgenFutures {
-        pub fn new<F>(f: F) -> Self
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn spawn<F>(&self, f: F)
         where
             F: Future<Output = ()> + 'static,
         {
             wasm_bindgen_futures::spawn_local(f);
-            Self {}
         }
     }
 }
@@ -29,33 +150,38 @@ mod native {
     use super::*;
 
     pub struct TokioRuntimeThread {
+        handle: tokio::runtime::Handle,
         join_handle: Option<std::thread::JoinHandle<()>>,
         quit_tx: tokio::sync::mpsc::UnboundedSender<()>,
     }
 
     impl TokioRuntimeThread {
-        pub fn new<F>(f: F) -> Self
-        where
-            F: Future + Send + 'static,
-            F::Output: Send,
-        {
+        pub fn new() -> Self {
             let (quit_tx, mut quit_rx) = tokio::sync::mpsc::unbounded_channel();
 
-            let join_handle = std::thread::spawn(move || {
-                let runtime = tokio::runtime::Builder::new_current_thread()
-                    .enable_all()
-                    .build()
-                    .expect("could not create the Tokio runtime, downloads will not work");
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("could not create the Tokio runtime, downloads will not work");
+            let handle = runtime.handle().to_owned();
 
-                runtime.spawn(f);
+            let join_handle = std::thread::spawn(move || {
                 runtime.block_on(quit_rx.recv());
             });
 
             Self {
+                handle,
                 join_handle: Some(join_handle),
                 quit_tx,
             }
         }
+
+        pub fn spawn<F>(&self, f: F)
+        where
+            F: Future<Output = ()> + Send + 'static,
+        {
+            self.handle.spawn(f);
+        }
     }
 
     impl Drop for TokioRuntimeThread {
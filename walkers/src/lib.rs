@@ -1,17 +1,43 @@
 #![doc = include_str!("../README.md")]
 #![deny(clippy::unwrap_used, rustdoc::broken_intra_doc_links)]
 
+mod animation;
+#[cfg(feature = "bing")]
+pub mod bing;
+mod disk_cache;
 mod download;
 pub mod extras;
+mod fetcher;
+mod filter;
+mod freshness;
 mod io;
 mod map;
+#[cfg(feature = "mbtiles")]
+pub mod mbtiles;
 pub mod mercator;
+mod offline;
+#[cfg(feature = "pmtiles")]
+pub mod pmtiles;
 pub mod providers;
+mod queue;
+#[cfg(feature = "tilejson")]
+pub mod tilejson;
 mod tiles;
+#[cfg(feature = "wmts")]
+pub mod wmts;
 mod zoom;
 
+pub use animation::Easing;
+pub use download::{BasicAuth, Credentials, HttpFetcher, HttpOptions, RetryPolicy};
+pub use fetcher::{BoxFuture, FetchError, Fetcher, TileData};
+pub use filter::ColorFilter;
+pub use geo_types::Point;
+pub use io::Downloader;
 pub use map::{Map, MapMemory, Plugin, Projector};
-pub use mercator::{screen_to_position, Position, Pixels};
-pub use tiles::Tiles;
+pub use mercator::{screen_to_position, Pixels, Position};
+pub use offline::{Estimate, Progress, Region, RegionDownload};
+pub use tiles::{Capacity, Tiles};
 pub use zoom::InvalidZoom;
-pub use geo_types::Point;
\ No newline at end of file
+
+/// Version of `reqwest` used by [`providers::TileSource::headers`] and [`Credentials`].
+pub use reqwest;
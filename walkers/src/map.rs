@@ -1,10 +1,14 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::Duration,
+};
 
-use egui::{Context, Mesh, Painter, Rect, Response, Sense, Ui, Vec2, Widget};
+use egui::{Color32, Context, Mesh, Painter, Rect, Response, Sense, Ui, Vec2, Widget};
 
 use crate::{
-    mercator::{screen_to_position, Pixels, PixelsExt, TileId},
-    tiles,
+    animation::{Animation, Easing},
+    mercator::{fit_bounds, screen_to_position, Pixels, PixelsExt, TileId},
+    queue::Priority,
     zoom::{InvalidZoom, Zoom},
     Position, Tiles,
 };
@@ -32,8 +36,11 @@ pub trait Plugin {
 ///     ));
 /// }
 /// ```
+///
+/// More layers of tiles, such as a transparent overlay, can be added with [`Map::with_layer`].
 pub struct Map<'a, 'b, 'c> {
-    tiles: Option<&'b mut Tiles>,
+    /// Layers of tiles with their opacity, bottom one first.
+    layers: Vec<(&'b mut Tiles, f32)>,
     memory: &'a mut MapMemory,
     my_position: Position,
     plugins: Vec<Box<dyn Plugin + 'c>>,
@@ -46,13 +53,21 @@ impl<'a, 'b, 'c> Map<'a, 'b, 'c> {
         my_position: Position,
     ) -> Self {
         Self {
-            tiles,
+            layers: tiles.into_iter().map(|tiles| (tiles, 1.)).collect(),
             memory,
             my_position,
             plugins: Vec::default(),
         }
     }
 
+    /// Add a layer of tiles, drawn on top of the previous ones, with opacity from 0 (invisible)
+    /// to 1 (opaque). Layers with zero opacity are not drawn and their tiles are not downloaded,
+    /// so this is also how a layer can be hidden.
+    pub fn with_layer(mut self, tiles: &'b mut Tiles, opacity: f32) -> Self {
+        self.layers.push((tiles, opacity.clamp(0., 1.)));
+        self
+    }
+
     /// Add plugin to the drawing pipeline. Plugins allow drawing custom shapes on the map.
     pub fn with_plugin(mut self, plugin: impl Plugin + 'c) -> Self {
         self.plugins.push(Box::new(plugin));
@@ -71,17 +86,17 @@ pub struct Projector {
 impl Projector {
     /// Project `position` into pixels on the viewport.
     pub fn project(&self, position: Position) -> Vec2 {
-        // Turn that into a flat, mercator projection.
-        let projected_position = position.project(self.memory.zoom.round());
+        let zoom = self.memory.zoom.into();
 
-        let zoom = self.memory.zoom.round();
+        // Turn that into a flat, mercator projection.
+        let projected_position = position.project(zoom);
 
         // We also need to know where the map center is.
         let map_center_projected_position = self
             .memory
             .center_mode
             .position(self.my_position, zoom)
-            .project(self.memory.zoom.round());
+            .project(zoom);
 
         // From the two points above we can calculate the actual point on the screen.
         self.clip_rect.center().to_vec2()
@@ -97,6 +112,9 @@ impl Map<'_, '_, '_> {
         // Zooming and dragging need to be exclusive, otherwise the map will get dragged when
         // pinch gesture is used.
         if !(0.99..=1.01).contains(&zoom_delta) && ui.ui_contains_pointer() {
+            // User takes over the camera.
+            self.memory.animation = None;
+
             // Displacement of mouse pointer relative to widget center
             let offset = response.hover_pos().map(|p| p - response.rect.center());
 
@@ -110,7 +128,7 @@ impl Map<'_, '_, '_> {
                     .center_mode
                     .clone()
                     .shift(-offset)
-                    .zero_offset(self.memory.zoom.round());
+                    .zero_offset(self.memory.zoom.into());
             }
 
             // Shift by 1 because of the values given by zoom_delta(). Multiple by 2, because
@@ -122,12 +140,16 @@ impl Map<'_, '_, '_> {
                 .memory
                 .center_mode
                 .clone()
-                .zero_offset(self.memory.zoom.round());
+                .zero_offset(self.memory.zoom.into());
 
             if let Some(offset) = offset {
                 self.memory.center_mode = self.memory.center_mode.clone().shift(offset);
             }
         } else {
+            if response.dragged_by(egui::PointerButton::Primary) {
+                self.memory.animation = None;
+            }
+
             self.memory
                 .center_mode
                 .recalculate_drag(response, self.my_position);
@@ -143,23 +165,35 @@ impl Widget for Map<'_, '_, '_> {
     fn ui(mut self, ui: &mut Ui) -> Response {
         let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::drag());
 
+        self.memory.animate(ui.ctx(), self.my_position, rect.size());
         self.zoom_and_drag(ui, &response);
 
-        let zoom = self.memory.zoom.round();
+        let zoom: f64 = self.memory.zoom.into();
         let map_center = self.memory.center_mode.position(self.my_position, zoom);
         let painter = ui.painter().with_clip_rect(rect);
 
-        if let Some(tiles) = self.tiles {
+        // Tiles of the nearest integer zoom, stretched or shrunk to the actual one.
+        let level = self.memory.zoom.round();
+        let scale = 2f64.powf(zoom - level as f64);
+
+        for (tiles, opacity) in self.layers.into_iter().filter(|(_, opacity)| *opacity > 0.) {
             let mut meshes = Default::default();
             flood_fill_tiles(
                 painter.clip_rect(),
-                map_center.tile_id(zoom, tiles.tile_size),
+                map_center.tile_id(level, tiles.tile_size),
                 map_center.project(zoom),
+                scale,
                 tiles,
                 &mut meshes,
             );
 
-            for shape in meshes.drain().filter_map(|(_, mesh)| mesh) {
+            let tint = Color32::WHITE.gamma_multiply(opacity);
+            for mut shape in meshes.drain().flat_map(|(_, meshes)| meshes) {
+                if opacity < 1. {
+                    for vertex in &mut shape.vertices {
+                        vertex.color = tint;
+                    }
+                }
                 painter.add(shape);
             }
         }
@@ -191,12 +225,12 @@ pub struct AdjustedPosition {
 
 impl AdjustedPosition {
     /// Calculate the real position, i.e. including the offset.
-    fn position(&self, zoom: u8) -> Position {
+    fn position(&self, zoom: f64) -> Position {
         screen_to_position(self.position.project(zoom) - self.offset, zoom)
     }
 
     /// Recalculate `position` so that `offset` is zero.
-    fn zero_offset(self, zoom: u8) -> Self {
+    fn zero_offset(self, zoom: f64) -> Self {
         Self {
             position: screen_to_position(self.position.project(zoom) - self.offset, zoom),
             offset: Default::default(),
@@ -282,7 +316,7 @@ impl Center {
 
     /// Returns exact position if map is detached (i.e. not following `my_position`),
     /// `None` otherwise.
-    fn detached(&self, zoom: u8) -> Option<Position> {
+    fn detached(&self, zoom: f64) -> Option<Position> {
         match self {
             Center::MyPosition => None,
             Center::Exact(position) | Center::Inertia { position, .. } => {
@@ -292,11 +326,11 @@ impl Center {
     }
 
     /// Get the real position at the map's center.
-    pub fn position(&self, my_position: Position, zoom: u8) -> Position {
+    pub fn position(&self, my_position: Position, zoom: f64) -> Position {
         self.detached(zoom).unwrap_or(my_position)
     }
 
-    pub fn zero_offset(self, zoom: u8) -> Self {
+    pub fn zero_offset(self, zoom: f64) -> Self {
         match self {
             Center::MyPosition => Center::MyPosition,
             Center::Exact(position) => Center::Exact(position.zero_offset(zoom)),
@@ -335,29 +369,35 @@ impl Center {
 pub struct MapMemory {
     pub center_mode: Center,
     pub zoom: Zoom,
+
+    /// Transition started by [`MapMemory::ease_to`] and alike.
+    animation: Option<Animation>,
 }
 
 impl MapMemory {
     /// Try to zoom in, returning `Err(InvalidZoom)` if already at maximum.
     pub fn zoom_in(&mut self) -> Result<(), InvalidZoom> {
-        self.center_mode = self.center_mode.clone().zero_offset(self.zoom.round());
+        self.animation = None;
+        self.center_mode = self.center_mode.clone().zero_offset(self.zoom.into());
         self.zoom.zoom_in()
     }
 
     /// Try to zoom out, returning `Err(InvalidZoom)` if already at minimum.
     pub fn zoom_out(&mut self) -> Result<(), InvalidZoom> {
-        self.center_mode = self.center_mode.clone().zero_offset(self.zoom.round());
+        self.animation = None;
+        self.center_mode = self.center_mode.clone().zero_offset(self.zoom.into());
         self.zoom.zoom_out()
     }
 
     /// Returns exact position if map is detached (i.e. not following `my_position`),
     /// `None` otherwise.
     pub fn detached(&self) -> Option<Position> {
-        self.center_mode.detached(self.zoom.round())
+        self.center_mode.detached(self.zoom.into())
     }
 
     /// Center exactly at the given position.
     pub fn center_at(&mut self, position: Position) {
+        self.animation = None;
         self.center_mode = Center::Exact(AdjustedPosition {
             position,
             offset: Default::default(),
@@ -366,28 +406,175 @@ impl MapMemory {
 
     /// Follow `my_position`.
     pub fn follow_my_position(&mut self) {
+        self.animation = None;
         self.center_mode = Center::MyPosition;
     }
+
+    /// Center and zoom the map, so all positions are visible, e.g. to show a whole route. A
+    /// bounding box can be given by its two opposite corners. `viewport` is the size of the map
+    /// widget, and `padding` is the free space left on each side, in pixels. Nothing happens if
+    /// there are no positions. For an animated version, use [`crate::mercator::fit_bounds`]
+    /// with [`MapMemory::fly_to`], limiting the zoom first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use walkers::{MapMemory, Position};
+    /// let mut map_memory = MapMemory::default();
+    /// map_memory.fit_bounds(
+    ///     [
+    ///         Position::from_lon_lat(16.8, 50.9),
+    ///         Position::from_lon_lat(17.3, 51.3),
+    ///     ],
+    ///     egui::Vec2::new(800., 600.),
+    ///     20.,
+    /// );
+    /// ```
+    pub fn fit_bounds(
+        &mut self,
+        positions: impl IntoIterator<Item = Position>,
+        viewport: Vec2,
+        padding: f32,
+    ) {
+        if let Some((position, zoom)) = fit_bounds(positions, viewport, padding) {
+            self.center_at(position);
+            self.zoom = Zoom::clamped(zoom as f32);
+        }
+    }
+
+    /// Smoothly move to the position and zoom over the given duration. Both change at the same
+    /// time, at the pace set by the easing. Dragging or zooming the map stops the animation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use walkers::{Easing, MapMemory, Position};
+    /// let mut map_memory = MapMemory::default();
+    /// map_memory
+    ///     .ease_to(
+    ///         Position::from_lon_lat(17.03664, 51.09916),
+    ///         16.,
+    ///         Duration::from_millis(500),
+    ///         Easing::EaseInOut,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn ease_to(
+        &mut self,
+        position: Position,
+        zoom: f32,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), InvalidZoom> {
+        let zoom = Zoom::try_from(zoom)?;
+        self.animation = Some(Animation::ease(
+            Some(position),
+            zoom.into(),
+            duration,
+            easing,
+        ));
+        Ok(())
+    }
+
+    /// Like [`MapMemory::ease_to`], but the map zooms out in the middle of the way, and back in
+    /// when getting closer, so it is clear where it goes. It is best for long distances, where
+    /// [`MapMemory::ease_to`] would just be a blur.
+    pub fn fly_to(
+        &mut self,
+        position: Position,
+        zoom: f32,
+        duration: Duration,
+    ) -> Result<(), InvalidZoom> {
+        let zoom = Zoom::try_from(zoom)?;
+        self.animation = Some(Animation::fly(position, zoom.into(), duration));
+        Ok(())
+    }
+
+    /// Smoothly change the zoom, keeping the center where it is.
+    pub fn zoom_to(
+        &mut self,
+        zoom: f32,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), InvalidZoom> {
+        let zoom = Zoom::try_from(zoom)?;
+        self.animation = Some(Animation::ease(None, zoom.into(), duration, easing));
+        Ok(())
+    }
+
+    /// Whether an animation started by [`MapMemory::ease_to`] and alike is in progress.
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// Move the camera according to the animation, if there is one.
+    fn animate(&mut self, ctx: &Context, my_position: Position, viewport: Vec2) {
+        let Some(animation) = &mut self.animation else {
+            return;
+        };
+
+        let zoom = self.zoom.into();
+        let current = (self.center_mode.position(my_position, zoom), zoom);
+        let ((position, zoom), finished) = animation.step(current, viewport);
+
+        self.center_mode = if animation.moves_center() {
+            Center::Exact(AdjustedPosition {
+                position,
+                offset: Default::default(),
+            })
+        } else {
+            // Map might be following `my_position`, which should not stop just due to zooming.
+            self.center_mode.clone().zero_offset(self.zoom.into())
+        };
+        self.zoom = Zoom::clamped(zoom as f32);
+
+        if finished {
+            self.animation = None;
+        } else {
+            // Same as with inertia, next frame is needed to continue the movement.
+            log::trace!("Requesting repaint due to animation.");
+            ctx.request_repaint();
+        }
+    }
 }
 
 /// Use simple [flood fill algorithm](https://en.wikipedia.org/wiki/Flood_fill) to draw tiles on the map.
+/// Tiles are drawn `scale` times larger than their actual size, which makes fractional zoom
+/// levels possible.
 fn flood_fill_tiles(
     viewport: Rect,
     tile_id: TileId,
     map_center_projected_position: Pixels,
+    scale: f64,
     tiles: &mut Tiles,
-    meshes: &mut HashMap<TileId, Option<Mesh>>,
+    meshes: &mut HashMap<TileId, Vec<Mesh>>,
 ) {
-    let tile_projected = tile_id.project(tiles.tile_size);
-    let tile_screen_position =
-        viewport.center().to_vec2() + (tile_projected - map_center_projected_position).to_vec2();
-
-    if viewport.intersects(tiles::rect(tile_screen_position, tiles.tile_size)) {
+    // Neighbouring tiles share their corners exactly, so there are no seams between them.
+    let screen_position = |x, y| {
+        let corner = TileId { x, y, ..tile_id }.project(tiles.tile_size) * scale;
+        viewport.center().to_vec2() + (corner - map_center_projected_position).to_vec2()
+    };
+    let rect = Rect::from_min_max(
+        screen_position(tile_id.x, tile_id.y).to_pos2(),
+        screen_position(tile_id.x + 1, tile_id.y + 1).to_pos2(),
+    );
+
+    // Tiles just outside the viewport are downloaded in advance, so they are ready when the map
+    // gets dragged.
+    let prefetch_area = viewport.expand(rect.width() / 2.);
+
+    if prefetch_area.intersects(rect) {
         if let Entry::Vacant(entry) = meshes.entry(tile_id) {
+            let distance = rect.center().distance(viewport.center());
+
             // It's still OK to insert an empty one, as we need to mark the spot for the filling algorithm.
-            let tile = tiles
-                .at(tile_id)
-                .map(|tile| tile.mesh(tile_screen_position, tiles.tile_size));
+            let tile = if viewport.intersects(rect) {
+                tiles.meshes(tile_id, rect, Priority::Visible { distance })
+            } else {
+                tiles.at(tile_id, Priority::Prefetch { distance });
+                Vec::new()
+            };
 
             entry.insert(tile);
 
@@ -404,6 +591,7 @@ fn flood_fill_tiles(
                     viewport,
                     *next_tile_id,
                     map_center_projected_position,
+                    scale,
                     tiles,
                     meshes,
                 );
@@ -411,3 +599,41 @@ fn flood_fill_tiles(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: Vec2 = Vec2::new(800., 600.);
+
+    #[test]
+    fn zooming_keeps_following_my_position() {
+        let my_position = Position::from_lon_lat(17.03664, 51.09916);
+        let mut map_memory = MapMemory::default();
+
+        map_memory
+            .zoom_to(10., Duration::ZERO, Easing::Linear)
+            .unwrap();
+        map_memory.animate(&Context::default(), my_position, VIEWPORT);
+
+        assert!(!map_memory.is_animating());
+        assert_eq!(Center::MyPosition, map_memory.center_mode);
+        assert_eq!(10., f64::from(map_memory.zoom));
+    }
+
+    #[test]
+    fn easing_detaches_the_map() {
+        let my_position = Position::from_lon_lat(17.03664, 51.09916);
+        let target = Position::from_lon_lat(21.00027, 52.26470);
+        let mut map_memory = MapMemory::default();
+
+        map_memory
+            .ease_to(target, 10., Duration::ZERO, Easing::Linear)
+            .unwrap();
+        map_memory.animate(&Context::default(), my_position, VIEWPORT);
+
+        let position = map_memory.detached().unwrap();
+        approx::assert_relative_eq!(target.lon(), position.lon(), epsilon = 1e-9);
+        approx::assert_relative_eq!(target.lat(), position.lat(), epsilon = 1e-9);
+    }
+}
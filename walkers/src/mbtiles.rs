@@ -0,0 +1,303 @@
+//! Raster tiles stored in a local [MBTiles](https://github.com/mapbox/mbtiles-spec) file, which
+//! is an SQLite database.
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+
+use crate::{
+    fetcher::{BoxFuture, FetchError, Fetcher, TileData},
+    io::in_background,
+    mercator::{TileId, TILE_SIZE},
+    providers::{Attribution, TileScheme},
+    Position,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Sqlite(rusqlite::Error),
+
+    #[error("unsupported tile format '{0}', only raster tiles are supported")]
+    UnsupportedFormat(String),
+}
+
+/// Contents of the MBTiles' `metadata` table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub name: Option<String>,
+
+    /// Format of the tile images, such as `png` or `jpg`.
+    pub format: Option<String>,
+
+    /// South-west and north-east corners of the area covered by the tiles.
+    pub bounds: Option<(Position, Position)>,
+
+    /// Default view of the map.
+    pub center: Option<Position>,
+
+    pub min_zoom: Option<u8>,
+    pub max_zoom: Option<u8>,
+
+    /// Attribution of the tiles, which might contain HTML.
+    pub attribution: Option<String>,
+
+    pub description: Option<String>,
+
+    /// All entries of the table, including the ones which have no dedicated field above.
+    pub entries: HashMap<String, String>,
+}
+
+impl Metadata {
+    fn from_entries(entries: HashMap<String, String>) -> Self {
+        let get = |name: &str| entries.get(name).cloned();
+        let numbers = |name: &str| -> Option<Vec<f64>> {
+            entries
+                .get(name)?
+                .split(',')
+                .map(|number| number.trim().parse().ok())
+                .collect()
+        };
+
+        Self {
+            name: get("name"),
+            format: get("format"),
+            bounds: numbers("bounds").and_then(|bounds| match bounds[..] {
+                [west, south, east, north] => Some((
+                    Position::from_lon_lat(west, south),
+                    Position::from_lon_lat(east, north),
+                )),
+                _ => None,
+            }),
+            center: numbers("center").and_then(|center| match center[..] {
+                [lon, lat, ..] => Some(Position::from_lon_lat(lon, lat)),
+                _ => None,
+            }),
+            min_zoom: get("minzoom").and_then(|zoom| zoom.parse().ok()),
+            max_zoom: get("maxzoom").and_then(|zoom| zoom.parse().ok()),
+            attribution: get("attribution"),
+            description: get("description"),
+            entries,
+        }
+    }
+}
+
+/// Opened MBTiles file. Pass it to [`crate::Tiles::with_fetcher`] to show it on the map.
+pub struct MbTiles {
+    // Connection can not be shared between threads by itself. It is also used by worker
+    // threads, see `Fetcher::fetch`.
+    connection: Arc<Mutex<Connection>>,
+    metadata: Metadata,
+    tile_size: u32,
+}
+
+impl MbTiles {
+    /// Open the file in read-only mode and read its metadata.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(Error::Sqlite)?;
+
+        let metadata = Metadata::from_entries(
+            connection
+                .prepare("SELECT name, value FROM metadata")
+                .and_then(|mut statement| {
+                    let entries: rusqlite::Result<HashMap<_, _>> = statement
+                        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                        .collect();
+                    entries
+                })
+                .map_err(Error::Sqlite)?,
+        );
+
+        if let Some(format) = metadata.format.as_deref().filter(|format| *format == "pbf") {
+            return Err(Error::UnsupportedFormat(format.to_owned()));
+        }
+
+        // Size is not a part of the metadata, so take a look at any tile.
+        let tile_size = connection
+            .query_row("SELECT tile_data FROM tiles LIMIT 1", [], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .optional()
+            .map_err(Error::Sqlite)?
+            .and_then(|tile| {
+                image::io::Reader::new(std::io::Cursor::new(tile))
+                    .with_guessed_format()
+                    .ok()?
+                    .into_dimensions()
+                    .ok()
+            })
+            .map_or(TILE_SIZE, |(width, _)| width);
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            metadata,
+            tile_size,
+        })
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Raw image of the tile, or `None` if the file does not contain it.
+    pub fn tile(&self, tile_id: TileId) -> Result<Option<Vec<u8>>, Error> {
+        read_tile(&lock(&self.connection), tile_id)
+    }
+}
+
+fn read_tile(connection: &Connection, tile_id: TileId) -> Result<Option<Vec<u8>>, Error> {
+    // MBTiles use TMS scheme, where Y axis goes from the south.
+    let Some(tile_row) = TileScheme::Tms.y(tile_id) else {
+        return Ok(None);
+    };
+
+    connection
+        .query_row(
+            "SELECT tile_data FROM tiles \
+             WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            (tile_id.zoom, tile_id.x, tile_row),
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(Error::Sqlite)
+}
+
+fn lock(connection: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    // Connection is always consistent, even if some thread panicked while holding the lock.
+    connection
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Fetcher for MbTiles {
+    fn fetch(&self, tile_id: TileId) -> BoxFuture<'_, Result<TileData, FetchError>> {
+        let connection = self.connection.clone();
+
+        Box::pin(async move {
+            // Queries read the disk, which would block the IO thread.
+            match in_background(move || read_tile(&lock(&connection), tile_id)).await {
+                Ok(Some(image)) => Ok(TileData::Bytes(image)),
+                Ok(None) => Err(FetchError::Permanent(
+                    format!("{:?} is not in the MBTiles file", tile_id).into(),
+                )),
+                Err(e) => Err(FetchError::Permanent(e.into())),
+            }
+        })
+    }
+
+    /// Taken from [`Metadata::attribution`], if there is one.
+    fn attribution(&self) -> Attribution {
+        Attribution::new(self.metadata.attribution.clone().unwrap_or_default(), "")
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    fn max_concurrent_fetches(&self) -> usize {
+        1
+    }
+
+    /// Taken from the [`Metadata`], if present.
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.metadata.min_zoom.unwrap_or(0)..=self.metadata.max_zoom.unwrap_or(19)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{queue::Priority, Downloader, Tiles};
+    use egui::Context;
+
+    /// Create a file with a single tile, at 1/0/0 in XYZ scheme.
+    fn mbtiles_file(directory: &Path) -> std::path::PathBuf {
+        let path = directory.join("test.mbtiles");
+        let connection = Connection::open(&path).unwrap();
+
+        connection
+            .execute_batch(
+                "CREATE TABLE metadata (name TEXT, value TEXT);
+                 CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER,
+                                     tile_data BLOB);
+                 INSERT INTO metadata VALUES ('name', 'Test');
+                 INSERT INTO metadata VALUES ('format', 'png');
+                 INSERT INTO metadata VALUES ('bounds', '16.8,50.9,17.3,51.3');
+                 INSERT INTO metadata VALUES ('minzoom', '0');
+                 INSERT INTO metadata VALUES ('maxzoom', '1');
+                 INSERT INTO metadata VALUES ('attribution', 'Someone');",
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO tiles VALUES (1, 0, 1, ?1)",
+                [include_bytes!("../assets/blank-255-tile.png").as_slice()],
+            )
+            .unwrap();
+
+        path
+    }
+
+    #[test]
+    fn metadata_is_read() {
+        let directory = tempfile::tempdir().unwrap();
+        let mbtiles = MbTiles::open(mbtiles_file(directory.path())).unwrap();
+        let metadata = mbtiles.metadata();
+
+        assert_eq!(Some("Test"), metadata.name.as_deref());
+        assert_eq!(Some("Someone"), metadata.attribution.as_deref());
+        assert_eq!(Some(0), metadata.min_zoom);
+        assert_eq!(Some(1), metadata.max_zoom);
+        assert_eq!(
+            Some((
+                Position::from_lon_lat(16.8, 50.9),
+                Position::from_lon_lat(17.3, 51.3)
+            )),
+            metadata.bounds
+        );
+        assert_eq!(256, Fetcher::tile_size(&mbtiles));
+        assert_eq!(0..=1, mbtiles.zoom_range());
+        assert_eq!("Someone", mbtiles.attribution().text);
+    }
+
+    #[test]
+    fn y_axis_is_flipped() {
+        let directory = tempfile::tempdir().unwrap();
+        let mbtiles = MbTiles::open(mbtiles_file(directory.path())).unwrap();
+
+        let north_west = TileId {
+            x: 0,
+            y: 0,
+            zoom: 1,
+        };
+        assert!(mbtiles.tile(north_west).unwrap().is_some());
+        assert!(mbtiles
+            .tile(TileId { y: 1, ..north_west })
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn tiles_are_read_from_mbtiles() {
+        let _ = env_logger::try_init();
+
+        let directory = tempfile::tempdir().unwrap();
+        let mbtiles = MbTiles::open(mbtiles_file(directory.path())).unwrap();
+        let mut tiles = Tiles::with_fetcher(mbtiles, &Downloader::new(), Context::default());
+
+        let tile_id = TileId {
+            x: 0,
+            y: 0,
+            zoom: 1,
+        };
+        while tiles.at(tile_id, Priority::default()).is_none() {}
+    }
+}
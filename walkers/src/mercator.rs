@@ -33,14 +33,15 @@ impl Position {
         self.0.x()
     }
 
-    /// Project geographical position into a 2D plane using Mercator.
-    pub fn project(&self, zoom: u8) -> Pixels {
+    /// Project geographical position into a 2D plane using Mercator. Zoom does not need to be
+    /// an integer, in which case the bitmap is scaled accordingly.
+    pub fn project(&self, zoom: f64) -> Pixels {
         let (x, y) = mercator_normalized(*self);
 
         // Map that into a big bitmap made out of web tiles.
-        let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
-        let x = x * number_of_pixels as f64;
-        let y = y * number_of_pixels as f64;
+        let number_of_pixels = total_pixels(zoom);
+        let x = x * number_of_pixels;
+        let y = y * number_of_pixels;
 
         Pixels::new(x, y)
     }
@@ -94,6 +95,11 @@ impl PixelsExt for Pixels {
 /// Size of the tiles used by the services like the OSM.
 pub(crate) const TILE_SIZE: u32 = 256;
 
+/// Width and height of the "World bitmap" at the given zoom.
+fn total_pixels(zoom: f64) -> f64 {
+    2f64.powf(zoom) * TILE_SIZE as f64
+}
+
 fn mercator_normalized(position: Position) -> (f64, f64) {
     // Project into Mercator (cylindrical map projection).
     let x = position.lon().to_radians();
@@ -157,12 +163,90 @@ impl TileId {
             zoom: self.zoom,
         })
     }
+
+    /// Tile one zoom level lower, which covers this one.
+    pub fn parent(&self) -> Option<TileId> {
+        Some(TileId {
+            x: self.x / 2,
+            y: self.y / 2,
+            zoom: self.zoom.checked_sub(1)?,
+        })
+    }
+
+    /// Four tiles one zoom level higher, which cover this one. They are ordered from the top
+    /// left one, row by row.
+    pub fn children(&self) -> [TileId; 4] {
+        let zoom = self.zoom + 1;
+        let (x, y) = (self.x * 2, self.y * 2);
+
+        [
+            TileId { x, y, zoom },
+            TileId { x: x + 1, y, zoom },
+            TileId { x, y: y + 1, zoom },
+            TileId {
+                x: x + 1,
+                y: y + 1,
+                zoom,
+            },
+        ]
+    }
+
+    /// [Quadkey](https://learn.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system)
+    /// of the tile, as used by Bing Maps. Each digit selects a quarter of the previous zoom's
+    /// tile.
+    pub fn quadkey(&self) -> String {
+        (1..=self.zoom)
+            .rev()
+            .map(|level| {
+                // Coordinates have no bits for levels beyond 32, so their digits are zeros.
+                let mask = 1u32.checked_shl(level as u32 - 1).unwrap_or(0);
+                let digit = (self.x & mask != 0) as u8 + 2 * (self.y & mask != 0) as u8;
+                char::from(b'0' + digit)
+            })
+            .collect()
+    }
+
+    /// Tile identified by the quadkey, or `None` if it is not a valid one. Inverse of
+    /// [`TileId::quadkey`].
+    pub fn from_quadkey(quadkey: &str) -> Option<TileId> {
+        // Deeper tiles would not fit in the coordinates.
+        if quadkey.len() > 32 {
+            return None;
+        }
+
+        quadkey.chars().try_fold(
+            TileId {
+                x: 0,
+                y: 0,
+                zoom: 0,
+            },
+            |tile_id, digit| {
+                let digit = digit.to_digit(4)?;
+                Some(TileId {
+                    x: tile_id.x * 2 + (digit & 1),
+                    y: tile_id.y * 2 + (digit >> 1),
+                    zoom: tile_id.zoom + 1,
+                })
+            },
+        )
+    }
+
+    /// Area covered by the tile in Web Mercator (EPSG:3857) meters, as
+    /// `[min_x, min_y, max_x, max_y]`.
+    pub fn web_mercator_bounds(&self) -> [f64; 4] {
+        // Half of the Earth's circumference at the equator.
+        let half = PI * 6_378_137.;
+        let span = 2. * half / 2f64.powi(self.zoom as i32);
+
+        let min_x = -half + self.x as f64 * span;
+        let max_y = half - self.y as f64 * span;
+        [min_x, max_y - span, min_x + span, max_y]
+    }
 }
 
 /// Transforms screen pixels into a geographical position.
-pub fn screen_to_position(pixels: Pixels, zoom: u8) -> Position {
-    let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
-    let number_of_pixels: f64 = number_of_pixels.into();
+pub fn screen_to_position(pixels: Pixels, zoom: f64) -> Position {
+    let number_of_pixels = total_pixels(zoom);
 
     let lon = pixels.x();
     let lon = lon / number_of_pixels;
@@ -177,11 +261,42 @@ pub fn screen_to_position(pixels: Pixels, zoom: u8) -> Position {
     Position::from_lon_lat(lon, lat)
 }
 
+/// Center and the largest zoom at which all positions fit in the viewport of the given size,
+/// with `padding` pixels of free space on each side. A bounding box can be given by its two
+/// opposite corners. Zoom might exceed what the map supports, in which case it needs to be
+/// limited. Returns `None` if there are no positions.
+pub fn fit_bounds(
+    positions: impl IntoIterator<Item = Position>,
+    viewport: egui::Vec2,
+    padding: f32,
+) -> Option<(Position, f64)> {
+    // Bitmap of zoom 0 is the smallest one, so everything else is its multiple.
+    let (min, max) = positions
+        .into_iter()
+        .map(|position| position.project(0.))
+        .fold(None, |bounds: Option<(Pixels, Pixels)>, pixels| {
+            Some(match bounds {
+                Some((min, max)) => (
+                    Pixels::new(min.x().min(pixels.x()), min.y().min(pixels.y())),
+                    Pixels::new(max.x().max(pixels.x()), max.y().max(pixels.y())),
+                ),
+                None => (pixels, pixels),
+            })
+        })?;
+
+    let available = (viewport - egui::Vec2::splat(2. * padding)).max(egui::Vec2::splat(1.));
+    let scale_x = available.x as f64 / (max.x() - min.x());
+    let scale_y = available.y as f64 / (max.y() - min.y());
+
+    // Single position fits at any zoom, so it is infinite then.
+    let zoom = scale_x.min(scale_y).log2();
+
+    Some((screen_to_position((min + max) / 2., 0.), zoom))
+}
 
 /// Transforms screen pixels into a geographical position.
-pub fn position_to_screen(pixels: Pixels, zoom: u8) -> Position {
-    let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
-    let number_of_pixels: f64 = number_of_pixels.into();
+pub fn position_to_screen(pixels: Pixels, zoom: f64) -> Position {
+    let number_of_pixels = total_pixels(zoom);
 
     let lon = pixels.x();
     let lon = lon / number_of_pixels;
@@ -235,22 +350,196 @@ mod tests {
 
         // Projected Citadel position should be somewhere near projected tile, shifted only by the
         // position on the tile.
-        let calculated = citadel.project(zoom);
+        let calculated = citadel.project(zoom as f64);
         let citadel_proj = Pixels::new(585455. * 256. + 184., 345104. * 256. + 116.5);
         approx::assert_relative_eq!(calculated.x(), citadel_proj.x(), max_relative = 0.5);
         approx::assert_relative_eq!(calculated.y(), citadel_proj.y(), max_relative = 0.5);
     }
 
+    #[test]
+    fn tile_parent_and_children() {
+        let tile_id = TileId {
+            x: 5,
+            y: 6,
+            zoom: 3,
+        };
+
+        assert_eq!(
+            Some(TileId {
+                x: 2,
+                y: 3,
+                zoom: 2
+            }),
+            tile_id.parent()
+        );
+
+        for child in tile_id.children() {
+            assert_eq!(Some(tile_id), child.parent());
+        }
+
+        let root = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        assert_eq!(None, root.parent());
+    }
+
+    #[test]
+    fn tile_quadkey() {
+        let tile_id = TileId {
+            x: 3,
+            y: 5,
+            zoom: 3,
+        };
+        assert_eq!("213", tile_id.quadkey());
+
+        let root = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        assert_eq!("", root.quadkey());
+
+        let deep = TileId {
+            x: 1,
+            y: 0,
+            zoom: 40,
+        };
+        assert_eq!(format!("{}1", "0".repeat(39)), deep.quadkey());
+    }
+
+    #[test]
+    fn tile_from_quadkey() {
+        assert_eq!(
+            Some(TileId {
+                x: 3,
+                y: 5,
+                zoom: 3
+            }),
+            TileId::from_quadkey("213")
+        );
+        assert_eq!(
+            Some(TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            }),
+            TileId::from_quadkey("")
+        );
+        assert_eq!(None, TileId::from_quadkey("214"));
+        assert_eq!(None, TileId::from_quadkey(&"3".repeat(33)));
+
+        let tile_id = TileId {
+            x: 12345,
+            y: 54321,
+            zoom: 17,
+        };
+        assert_eq!(Some(tile_id), TileId::from_quadkey(&tile_id.quadkey()));
+    }
+
+    #[test]
+    fn tile_web_mercator_bounds() {
+        let half = 20_037_508.342_789_244;
+
+        let root = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        for (expected, actual) in [-half, -half, half, half]
+            .iter()
+            .zip(root.web_mercator_bounds())
+        {
+            approx::assert_relative_eq!(*expected, actual, epsilon = 1e-6);
+        }
+
+        let south_east = TileId {
+            x: 1,
+            y: 1,
+            zoom: 1,
+        };
+        for (expected, actual) in [0., -half, half, 0.]
+            .iter()
+            .zip(south_east.web_mercator_bounds())
+        {
+            approx::assert_relative_eq!(*expected, actual, epsilon = 1e-6);
+        }
+    }
+
     #[test]
     fn project_there_and_back() {
         let citadel = Position::from_lat_lon(21.00027, 52.26470);
-        let zoom = 16;
+        let zoom = 16.;
         let calculated = screen_to_position(citadel.project(zoom), zoom);
 
         approx::assert_relative_eq!(calculated.lon(), citadel.lon(), max_relative = 1.0);
         approx::assert_relative_eq!(calculated.lat(), citadel.lat(), max_relative = 1.0);
     }
 
+    #[test]
+    fn bounds_fit_in_viewport() {
+        let south_west = Position::from_lon_lat(16.8, 50.9);
+        let north_east = Position::from_lon_lat(17.3, 51.3);
+        let viewport = egui::Vec2::new(800., 600.);
+
+        let (center, zoom) = fit_bounds([north_east, south_west], viewport, 50.).unwrap();
+
+        // Bounding box touches the padding from the top and the bottom.
+        let (north_east, south_west) = (north_east.project(zoom), south_west.project(zoom));
+        approx::assert_relative_eq!(500., south_west.y() - north_east.y(), max_relative = 1e-9);
+        assert!(north_east.x() - south_west.x() < 700.);
+
+        // And it is centered.
+        let center = center.project(zoom);
+        approx::assert_relative_eq!(
+            center.x() - south_west.x(),
+            north_east.x() - center.x(),
+            max_relative = 1e-9
+        );
+        approx::assert_relative_eq!(
+            center.y() - north_east.y(),
+            south_west.y() - center.y(),
+            max_relative = 1e-9
+        );
+    }
+
+    #[test]
+    fn single_position_fits_at_any_zoom() {
+        let citadel = Position::from_lon_lat(21.00027, 52.26470);
+        let viewport = egui::Vec2::new(800., 600.);
+
+        let (center, zoom) = fit_bounds([citadel], viewport, 0.).unwrap();
+        approx::assert_relative_eq!(citadel.lon(), center.lon(), epsilon = 1e-9);
+        approx::assert_relative_eq!(citadel.lat(), center.lat(), epsilon = 1e-9);
+        assert_eq!(f64::INFINITY, zoom);
+
+        assert!(fit_bounds([], viewport, 0.).is_none());
+    }
+
+    #[test]
+    fn projecting_with_fractional_zoom() {
+        let citadel = Position::from_lon_lat(21.00027, 52.26470);
+
+        // Half a zoom level scales the bitmap by the square root of two.
+        let scale = 2f64.sqrt();
+        let projected = citadel.project(16.5);
+        approx::assert_relative_eq!(
+            projected.x(),
+            citadel.project(16.).x() * scale,
+            max_relative = 1e-12
+        );
+        approx::assert_relative_eq!(
+            projected.y(),
+            citadel.project(16.).y() * scale,
+            max_relative = 1e-12
+        );
+
+        let calculated = screen_to_position(projected, 16.5);
+        approx::assert_relative_eq!(calculated.lon(), citadel.lon(), epsilon = 1e-9);
+        approx::assert_relative_eq!(calculated.lat(), citadel.lat(), epsilon = 1e-9);
+    }
+
     #[test]
     /// Just to be compatible with the `geo` ecosystem.
     fn position_is_compatible_with_geo_types() {
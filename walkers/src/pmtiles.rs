@@ -0,0 +1,814 @@
+//! Raster tiles stored in a single [PMTiles](https://github.com/protomaps/PMTiles) v3 archive,
+//! either a local file or one hosted by a static HTTP server, which supports range requests.
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, SeekFrom},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use reqwest::{
+    header::{HeaderMap, RANGE},
+    StatusCode,
+};
+
+use crate::{
+    download::{authorize, http_failure, report_rejected, tile_request, Credentials, HttpOptions},
+    fetcher::{BoxFuture, Failure, FetchError, Fetcher, TileData},
+    io::in_background,
+    mercator::{TileId, TILE_SIZE},
+    providers::Attribution,
+    Position,
+};
+
+const HEADER_SIZE: usize = 127;
+
+/// Header and the root directory are guaranteed to fit in that many first bytes of the archive,
+/// so both can be read at once.
+const ROOT_SIZE: u64 = 16384;
+
+/// Root directory plus at most three levels of leaf directories.
+const MAX_DEPTH: usize = 4;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Http(reqwest::Error),
+
+    #[error(transparent)]
+    Io(std::io::Error),
+
+    #[error(transparent)]
+    Credentials(FetchError),
+
+    #[error("not a PMTiles archive")]
+    InvalidHeader,
+
+    #[error("unsupported PMTiles version {0}, only 3 is supported")]
+    UnsupportedVersion(u8),
+
+    #[error("unsupported compression {0:?}")]
+    UnsupportedCompression(Compression),
+
+    #[error("directory is malformed")]
+    InvalidDirectory,
+}
+
+impl From<Error> for FetchError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Credentials(e) => e,
+            Error::Http(ref http) if http_failure(http) == Failure::Transient => {
+                FetchError::Transient(e.into())
+            }
+            e => FetchError::Permanent(e.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Unknown,
+    None,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl From<u8> for Compression {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::None,
+            2 => Self::Gzip,
+            3 => Self::Brotli,
+            4 => Self::Zstd,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileType {
+    Unknown,
+    Mvt,
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl From<u8> for TileType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::Mvt,
+            2 => Self::Png,
+            3 => Self::Jpeg,
+            4 => Self::Webp,
+            5 => Self::Avif,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Fixed-size header at the beginning of the archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    root_directory: (u64, u64),
+    leaf_directories_offset: u64,
+    tile_data_offset: u64,
+
+    /// Location of the JSON metadata.
+    metadata: (u64, u64),
+
+    pub internal_compression: Compression,
+    pub tile_compression: Compression,
+    pub tile_type: TileType,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+
+    /// South-west and north-east corners of the area covered by the tiles.
+    pub bounds: (Position, Position),
+
+    /// Default view of the map.
+    pub center: Position,
+    pub center_zoom: u8,
+}
+
+impl Header {
+    fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE || &bytes[..7] != b"PMTiles" {
+            return Err(Error::InvalidHeader);
+        }
+
+        if bytes[7] != 3 {
+            return Err(Error::UnsupportedVersion(bytes[7]));
+        }
+
+        let u64_at = |offset: usize| {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap_or_default())
+        };
+        let degrees_at = |offset: usize| {
+            i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap_or_default()) as f64
+                / 10_000_000.
+        };
+
+        // Each of these is read in one go, so its end must not overflow either.
+        let range_at = |offset: usize| {
+            let range = (u64_at(offset), u64_at(offset + 8));
+            range.0.checked_add(range.1).ok_or(Error::InvalidHeader)?;
+            Ok(range)
+        };
+
+        Ok(Self {
+            root_directory: range_at(8)?,
+            metadata: range_at(24)?,
+            leaf_directories_offset: u64_at(40),
+            tile_data_offset: u64_at(56),
+            internal_compression: bytes[97].into(),
+            tile_compression: bytes[98].into(),
+            tile_type: bytes[99].into(),
+            min_zoom: bytes[100],
+            max_zoom: bytes[101],
+            bounds: (
+                Position::from_lon_lat(degrees_at(102), degrees_at(106)),
+                Position::from_lon_lat(degrees_at(110), degrees_at(114)),
+            ),
+            center_zoom: bytes[118],
+            center: Position::from_lon_lat(degrees_at(119), degrees_at(123)),
+        })
+    }
+}
+
+/// Single entry of a directory. If `run_length` is zero, it points to a leaf directory,
+/// otherwise to `run_length` consecutive tiles sharing the same data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    run_length: u64,
+}
+
+type Directory = Arc<Vec<Entry>>;
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Result<u64, Error> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = bytes.next().ok_or(Error::InvalidDirectory)?;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(Error::InvalidDirectory)
+}
+
+/// Parse already decompressed directory. Each column is stored separately, offsets are
+/// relative to the previous entry where possible.
+fn parse_directory(bytes: &[u8]) -> Result<Vec<Entry>, Error> {
+    let mut bytes = bytes.iter().copied();
+    let count = read_varint(&mut bytes)? as usize;
+
+    // Do not trust the count for the allocation, it might be garbage.
+    let mut entries = Vec::with_capacity(count.min(bytes.len()));
+
+    let mut tile_id = 0;
+    for _ in 0..count {
+        tile_id = read_varint(&mut bytes)?
+            .checked_add(tile_id)
+            .ok_or(Error::InvalidDirectory)?;
+        entries.push(Entry {
+            tile_id,
+            offset: 0,
+            length: 0,
+            run_length: 0,
+        });
+    }
+
+    for entry in &mut entries {
+        entry.run_length = read_varint(&mut bytes)?;
+    }
+
+    for entry in &mut entries {
+        entry.length = read_varint(&mut bytes)?;
+    }
+
+    let mut previous: Option<Entry> = None;
+    for entry in &mut entries {
+        let offset = read_varint(&mut bytes)?;
+        entry.offset = match (offset, previous) {
+            (0, Some(previous)) => previous
+                .offset
+                .checked_add(previous.length)
+                .ok_or(Error::InvalidDirectory)?,
+            _ => offset.checked_sub(1).ok_or(Error::InvalidDirectory)?,
+        };
+        previous = Some(*entry);
+    }
+
+    Ok(entries)
+}
+
+/// Position of the tile on the Hilbert curve, counting tiles of all lower zoom levels first.
+/// `None` if it does not fit in `u64`, so no archive can have such tile.
+fn hilbert_tile_id(tile_id: TileId) -> Option<u64> {
+    let zoom = tile_id.zoom as u32;
+    if zoom > 31 {
+        return None;
+    }
+
+    let tiles_on_lower_zooms = (4u64.pow(zoom) - 1) / 3;
+
+    let (mut x, mut y) = (tile_id.x as u64, tile_id.y as u64);
+    let mut position = 0;
+    let mut s = (1u64 << zoom) / 2;
+
+    while s > 0 {
+        let rx = ((x & s) > 0) as u64;
+        let ry = ((y & s) > 0) as u64;
+        position += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant.
+        if ry == 0 {
+            if rx == 1 {
+                x = s.wrapping_sub(1).wrapping_sub(x);
+                y = s.wrapping_sub(1).wrapping_sub(y);
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        s /= 2;
+    }
+
+    Some(tiles_on_lower_zooms + position)
+}
+
+/// Find the entry covering the tile, which might be a leaf directory.
+fn find(entries: &[Entry], tile_id: u64) -> Option<Entry> {
+    let index = entries
+        .partition_point(|entry| entry.tile_id <= tile_id)
+        .checked_sub(1)?;
+    let entry = entries[index];
+
+    if entry.run_length == 0 || tile_id - entry.tile_id < entry.run_length {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+fn decompress(data: Vec<u8>, compression: Compression) -> Result<Vec<u8>, Error> {
+    match compression {
+        Compression::None | Compression::Unknown => Ok(data),
+        Compression::Gzip => {
+            let mut decompressed = Vec::new();
+            flate2::read::GzDecoder::new(data.as_slice())
+                .read_to_end(&mut decompressed)
+                .map_err(Error::Io)?;
+            Ok(decompressed)
+        }
+        compression => Err(Error::UnsupportedCompression(compression)),
+    }
+}
+
+enum Backend {
+    File(PathBuf),
+    Http {
+        client: reqwest::Client,
+        url: String,
+        headers: HeaderMap,
+        credentials: Option<Arc<dyn Credentials>>,
+    },
+}
+
+impl Backend {
+    /// Read up to `length` bytes. Fewer are returned only if the archive ends earlier.
+    async fn read(&self, offset: u64, length: u64) -> Result<Vec<u8>, Error> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        match self {
+            Backend::File(path) => read_file(path, offset, length),
+            Backend::Http {
+                client,
+                url,
+                headers,
+                credentials,
+            } => {
+                let end = offset.saturating_add(length - 1);
+                let request = tile_request(client, url, headers.clone())
+                    .header(RANGE, format!("bytes={}-{}", offset, end));
+                let request = authorize(request, credentials.as_ref())
+                    .await
+                    .map_err(Error::Credentials)?;
+
+                let response = request.send().await.map_err(Error::Http)?;
+                report_rejected(response.status(), credentials.as_ref());
+                let response = response.error_for_status().map_err(Error::Http)?;
+
+                let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+                let data = response.bytes().await.map_err(Error::Http)?;
+
+                // Server might ignore the range and send the whole archive.
+                let data = if partial {
+                    &data[..]
+                } else {
+                    let start = (offset as usize).min(data.len());
+                    let end = (end as usize).saturating_add(1).min(data.len());
+                    &data[start..end]
+                };
+
+                Ok(data.to_vec())
+            }
+        }
+    }
+
+    /// Read the section, unless it is already in the `data` read from the archive's beginning.
+    async fn section(&self, data: &[u8], (offset, length): (u64, u64)) -> Result<Vec<u8>, Error> {
+        // Header guarantees that the end does not overflow.
+        match data.get(offset as usize..(offset + length) as usize) {
+            Some(section) => Ok(section.to_vec()),
+            None => self.read(offset, length).await,
+        }
+    }
+}
+
+fn read_file(path: &Path, offset: u64, length: u64) -> Result<Vec<u8>, Error> {
+    let mut file = std::fs::File::open(path).map_err(Error::Io)?;
+    file.seek(SeekFrom::Start(offset)).map_err(Error::Io)?;
+
+    let mut data = Vec::new();
+    file.take(length)
+        .read_to_end(&mut data)
+        .map_err(Error::Io)?;
+    Ok(data)
+}
+
+/// Backend along with the directories, shared with the worker threads.
+struct Archive {
+    backend: Backend,
+    header: Header,
+    root: Directory,
+    leaves: Mutex<HashMap<u64, Directory>>,
+}
+
+/// PMTiles archive. Pass it to [`crate::Tiles::with_fetcher`] to show it on the map. Only raster
+/// tiles are supported.
+pub struct PmTiles {
+    archive: Arc<Archive>,
+    metadata: String,
+    attribution: Attribution,
+    tile_size: u32,
+}
+
+impl PmTiles {
+    /// Open an archive stored in a local file. Its async methods read the file right away, so
+    /// they work with any executor, but block it for the time of reading.
+    pub async fn file(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        Self::open(Backend::File(path.into())).await
+    }
+
+    /// Open an archive hosted by an HTTP server. It is read with range requests, so only the
+    /// needed parts are downloaded. `headers` are sent with each request, like
+    /// [`crate::providers::TileSource::headers`], and the options give the client and the
+    /// credentials. The disk cache and the retry policy of the options are not used, as tiles
+    /// are not downloaded one by one.
+    pub async fn http(
+        url: impl Into<String>,
+        headers: HeaderMap,
+        http_options: &HttpOptions,
+    ) -> Result<Self, Error> {
+        Self::open(Backend::Http {
+            client: http_options.client(),
+            url: url.into(),
+            headers,
+            credentials: http_options.credentials.clone(),
+        })
+        .await
+    }
+
+    /// Read the header, the root directory and the metadata, which are kept for all lookups.
+    async fn open(backend: Backend) -> Result<Self, Error> {
+        let data = backend.read(0, ROOT_SIZE).await?;
+        let header = Header::parse(&data)?;
+
+        let root = backend.section(&data, header.root_directory).await?;
+        let root = parse_directory(&decompress(root, header.internal_compression)?)?;
+
+        let metadata = backend.section(&data, header.metadata).await?;
+        let metadata = decompress(metadata, header.internal_compression)?;
+        let metadata = String::from_utf8_lossy(&metadata).into_owned();
+
+        Ok(Self {
+            attribution: attribution(&metadata),
+            metadata,
+            archive: Arc::new(Archive {
+                backend,
+                header,
+                root: Arc::new(root),
+                leaves: Default::default(),
+            }),
+            tile_size: TILE_SIZE,
+        })
+    }
+
+    /// Size of the tiles in the archive, which is not stored in the header. Default is 256.
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.archive.header
+    }
+
+    /// Archive's metadata, which is a JSON document.
+    pub fn metadata(&self) -> &str {
+        &self.metadata
+    }
+
+    /// Raw image of the tile, or `None` if the archive does not contain it.
+    pub async fn tile(&self, tile_id: TileId) -> Result<Option<Vec<u8>>, Error> {
+        self.archive.tile(tile_id).await
+    }
+}
+
+/// Attribution found in the metadata. The key is not required, but commonly used.
+fn attribution(metadata: &str) -> Attribution {
+    let text = serde_json::from_str::<serde_json::Value>(metadata)
+        .ok()
+        .and_then(|metadata| Some(metadata.get("attribution")?.as_str()?.to_owned()))
+        .unwrap_or_default();
+    Attribution::new(text, "")
+}
+
+impl Archive {
+    async fn tile(&self, tile_id: TileId) -> Result<Option<Vec<u8>>, Error> {
+        let Some(hilbert_tile_id) = hilbert_tile_id(tile_id) else {
+            return Ok(None);
+        };
+        let mut directory = self.root.clone();
+
+        for _ in 0..MAX_DEPTH {
+            let Some(entry) = find(&directory, hilbert_tile_id) else {
+                return Ok(None);
+            };
+
+            if entry.run_length > 0 {
+                let offset = absolute_offset(self.header.tile_data_offset, &entry)?;
+                let data = self.backend.read(offset, entry.length).await?;
+                return decompress(data, self.header.tile_compression).map(Some);
+            }
+
+            directory = self.leaf(&entry).await?;
+        }
+
+        Err(Error::InvalidDirectory)
+    }
+
+    async fn leaf(&self, entry: &Entry) -> Result<Directory, Error> {
+        let cached = lock(&self.leaves).get(&entry.offset).cloned();
+        if let Some(directory) = cached {
+            return Ok(directory);
+        }
+
+        let data = self
+            .backend
+            .read(
+                absolute_offset(self.header.leaf_directories_offset, entry)?,
+                entry.length,
+            )
+            .await?;
+        let directory = Arc::new(parse_directory(&decompress(
+            data,
+            self.header.internal_compression,
+        )?)?);
+
+        lock(&self.leaves).insert(entry.offset, directory.clone());
+        Ok(directory)
+    }
+}
+
+/// Offset of the entry from the beginning of the archive, as long as its whole data fits there.
+fn absolute_offset(section_offset: u64, entry: &Entry) -> Result<u64, Error> {
+    let offset = section_offset
+        .checked_add(entry.offset)
+        .ok_or(Error::InvalidDirectory)?;
+    offset
+        .checked_add(entry.length)
+        .ok_or(Error::InvalidDirectory)?;
+    Ok(offset)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Values are always consistent, even if some thread panicked while holding the lock.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Fetcher for PmTiles {
+    fn fetch(&self, tile_id: TileId) -> BoxFuture<'_, Result<TileData, FetchError>> {
+        Box::pin(async move {
+            let tile = match self.archive.backend {
+                // Reading the disk would block the IO thread.
+                Backend::File(_) => {
+                    let archive = self.archive.clone();
+                    in_background(move || futures::executor::block_on(archive.tile(tile_id))).await
+                }
+                Backend::Http { .. } => self.archive.tile(tile_id).await,
+            };
+
+            match tile {
+                Ok(Some(image)) => Ok(TileData::Bytes(image)),
+                Ok(None) => Err(FetchError::Permanent(
+                    format!("{:?} is not in the PMTiles archive", tile_id).into(),
+                )),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    /// Attribution found in [`PmTiles::metadata`], if any.
+    fn attribution(&self) -> Attribution {
+        self.attribution.clone()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.archive.header.min_zoom..=self.archive.header.max_zoom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{queue::Priority, Downloader, Tiles};
+    use egui::Context;
+    use futures::executor::block_on;
+
+    const TILE: &[u8] = include_bytes!("../assets/blank-255-tile.png");
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    /// Uncompressed archive with the same tile at 1/0/0 and 1/0/1.
+    fn archive() -> Vec<u8> {
+        let mut directory = Vec::new();
+        varint(1, &mut directory); // Number of entries.
+        varint(
+            hilbert_tile_id(TileId {
+                x: 0,
+                y: 0,
+                zoom: 1,
+            })
+            .unwrap(),
+            &mut directory,
+        );
+        varint(2, &mut directory); // Run length.
+        varint(TILE.len() as u64, &mut directory);
+        varint(1, &mut directory); // Offset + 1.
+
+        let metadata = br#"{"name":"Test","attribution":"Someone"}"#;
+        let root_offset = HEADER_SIZE as u64;
+        let metadata_offset = root_offset + directory.len() as u64;
+        let tile_data_offset = metadata_offset + metadata.len() as u64;
+
+        let mut header = b"PMTiles\x03".to_vec();
+        for value in [
+            root_offset,
+            directory.len() as u64,
+            metadata_offset,
+            metadata.len() as u64,
+            tile_data_offset, // No leaf directories.
+            0,
+            tile_data_offset,
+            TILE.len() as u64,
+            2, // Addressed tiles.
+            1, // Tile entries.
+            1, // Tile contents.
+        ] {
+            header.extend(value.to_le_bytes());
+        }
+        header.extend([1, 1, 1, 2, 0, 1]); // Clustered, compressions, type, zooms.
+        for degrees in [16.8, 50.9, 17.3, 51.3] {
+            header.extend(((degrees * 10_000_000.) as i32).to_le_bytes());
+        }
+        header.push(1);
+        for degrees in [17.0, 51.1] {
+            header.extend(((degrees * 10_000_000.) as i32).to_le_bytes());
+        }
+        assert_eq!(HEADER_SIZE, header.len());
+
+        [header, directory, metadata.to_vec(), TILE.to_vec()].concat()
+    }
+
+    #[test]
+    fn hilbert_curve() {
+        let tile = |x, y, zoom| hilbert_tile_id(TileId { x, y, zoom });
+
+        assert_eq!(Some(0), tile(0, 0, 0));
+        assert_eq!(Some(1), tile(0, 0, 1));
+        assert_eq!(Some(2), tile(0, 1, 1));
+        assert_eq!(Some(3), tile(1, 1, 1));
+        assert_eq!(Some(4), tile(1, 0, 1));
+        assert_eq!(Some(5), tile(0, 0, 2));
+        assert_eq!(Some(20), tile(3, 0, 2));
+        assert!(tile(0, 0, 31).is_some());
+        assert_eq!(None, tile(0, 0, 32));
+    }
+
+    #[test]
+    fn tiles_are_read_from_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("test.pmtiles");
+        std::fs::write(&path, archive()).unwrap();
+
+        let pmtiles = block_on(PmTiles::file(path)).unwrap();
+
+        let header = pmtiles.header();
+        assert_eq!(TileType::Png, header.tile_type);
+        assert_eq!((0, 1), (header.min_zoom, header.max_zoom));
+        assert_eq!(1, header.center_zoom);
+        assert_eq!(0..=1, pmtiles.zoom_range());
+
+        assert_eq!(
+            r#"{"name":"Test","attribution":"Someone"}"#,
+            pmtiles.metadata()
+        );
+        assert_eq!("Someone", pmtiles.attribution().text);
+
+        block_on(async {
+            let tile = |x, y, zoom| pmtiles.tile(TileId { x, y, zoom });
+            assert_eq!(Some(TILE.to_vec()), tile(0, 0, 1).await.unwrap());
+            assert_eq!(Some(TILE.to_vec()), tile(0, 1, 1).await.unwrap());
+            assert_eq!(None, tile(1, 1, 1).await.unwrap());
+            assert_eq!(None, tile(0, 0, 0).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn file_is_read_on_a_worker_thread() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("test.pmtiles");
+        std::fs::write(&path, archive()).unwrap();
+
+        let pmtiles = block_on(PmTiles::file(path)).unwrap();
+        let mut tiles = Tiles::with_fetcher(pmtiles, &Downloader::new(), Context::default());
+
+        let tile_id = TileId {
+            x: 0,
+            y: 1,
+            zoom: 1,
+        };
+        while tiles.at(tile_id, Priority::default()).is_none() {}
+    }
+
+    #[test]
+    fn garbage_is_not_an_archive() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("test.pmtiles");
+        std::fs::write(&path, "definitely not an archive").unwrap();
+
+        assert!(matches!(
+            block_on(PmTiles::file(path)),
+            Err(Error::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn truncated_header_is_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("test.pmtiles");
+        std::fs::write(&path, &archive()[..HEADER_SIZE - 1]).unwrap();
+
+        assert!(matches!(
+            block_on(PmTiles::file(path)),
+            Err(Error::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn overflowing_offsets_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let with_offset = |at: usize| {
+            let mut archive = archive();
+            archive[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+            let path = directory.path().join(format!("{}.pmtiles", at));
+            std::fs::write(&path, archive).unwrap();
+            block_on(PmTiles::file(path))
+        };
+        let tile_id = TileId {
+            x: 0,
+            y: 0,
+            zoom: 1,
+        };
+
+        // Root directory.
+        assert!(matches!(with_offset(8), Err(Error::InvalidHeader)));
+
+        // Tile data.
+        let pmtiles = with_offset(56).unwrap();
+        assert!(matches!(
+            block_on(pmtiles.tile(tile_id)),
+            Err(Error::InvalidDirectory)
+        ));
+    }
+
+    #[test]
+    fn tiles_are_read_using_http_range_requests() {
+        let _ = env_logger::try_init();
+
+        let archive = archive();
+        let tile_offset = archive.len() - TILE.len();
+
+        let mut server = mockito::Server::new();
+        let root_mock = server
+            .mock("GET", "/test.pmtiles")
+            .match_header("range", "bytes=0-16383")
+            .with_status(206)
+            .with_body(&archive)
+            .expect(1)
+            .create();
+        let tile_mock = server
+            .mock("GET", "/test.pmtiles")
+            .match_header(
+                "range",
+                format!("bytes={}-{}", tile_offset, archive.len() - 1).as_str(),
+            )
+            .with_status(206)
+            .with_body(TILE)
+            .create();
+
+        let pmtiles = crate::io::block_on(PmTiles::http(
+            format!("{}/test.pmtiles", server.url()),
+            HeaderMap::new(),
+            &HttpOptions::default(),
+        ))
+        .unwrap();
+        let mut tiles = Tiles::with_fetcher(pmtiles, &Downloader::new(), Context::default());
+
+        let tile_id = TileId {
+            x: 0,
+            y: 0,
+            zoom: 1,
+        };
+        while tiles.at(tile_id, Priority::default()).is_none() {}
+
+        root_mock.assert();
+        tile_mock.assert();
+    }
+}
@@ -1,15 +1,34 @@
 //! Some common tile map providers.
 
-use crate::mercator::TileId;
+use std::{borrow::Cow, ops::RangeInclusive, path::PathBuf};
 
-#[derive(Clone)]
+use reqwest::header::HeaderMap;
+
+use crate::{
+    fetcher::{BoxFuture, FetchError, Fetcher, TileData},
+    io::in_background,
+    mercator::TileId,
+};
+
+#[derive(Clone, Default)]
 pub struct Attribution {
-    pub text: &'static str,
-    pub url: &'static str,
+    pub text: Cow<'static, str>,
+    pub url: Cow<'static, str>,
     pub logo_light: Option<egui::ImageSource<'static>>,
     pub logo_dark: Option<egui::ImageSource<'static>>,
 }
 
+impl Attribution {
+    /// Attribution without logos, e.g. one which is known only at runtime.
+    pub fn new(text: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            text: Cow::Owned(text.into()),
+            url: Cow::Owned(url.into()),
+            ..Default::default()
+        }
+    }
+}
+
 pub trait TileSource {
     fn tile_url(&self, tile_id: TileId) -> String;
     fn attribution(&self) -> Attribution;
@@ -18,6 +37,27 @@ pub trait TileSource {
     fn tile_size(&self) -> u32 {
         256
     }
+
+    /// Maximum number of tiles being downloaded at the same time. Check the provider's usage
+    /// policy before increasing it.
+    fn max_concurrent_downloads(&self) -> usize {
+        6
+    }
+
+    /// Zoom levels for which the source has tiles. Beyond the maximum, the most detailed tiles
+    /// get stretched.
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        0..=19
+    }
+
+    /// Headers sent with every tile request, such as `User-Agent` and `Referer`, which some
+    /// providers' usage policies require, or an API key. They replace the default ones. It is
+    /// called for each request, so it may return a different value each time. Credentials
+    /// which need to be obtained asynchronously can be given with
+    /// [`crate::HttpOptions::credentials`].
+    fn headers(&self) -> HeaderMap {
+        HeaderMap::new()
+    }
 }
 
 /// <https://www.openstreetmap.org/about>
@@ -33,10 +73,9 @@ impl TileSource for OpenStreetMap {
 
     fn attribution(&self) -> Attribution {
         Attribution {
-            text: "OpenStreetMap contributors",
-            url: "https://www.openstreetmap.org/copyright",
-            logo_light: None,
-            logo_dark: None,
+            text: "OpenStreetMap contributors".into(),
+            url: "https://www.openstreetmap.org/copyright".into(),
+            ..Default::default()
         }
     }
 }
@@ -63,10 +102,9 @@ impl TileSource for Geoportal {
 
     fn attribution(&self) -> Attribution {
         Attribution {
-            text: "Główny Urząd Geodezji i Kartografii",
-            url: "https://www.geoportal.gov.pl/",
-            logo_light: None,
-            logo_dark: None,
+            text: "Główny Urząd Geodezji i Kartografii".into(),
+            url: "https://www.geoportal.gov.pl/".into(),
+            ..Default::default()
         }
     }
 }
@@ -129,8 +167,8 @@ impl TileSource for Mapbox {
     fn attribution(&self) -> Attribution {
         // TODO: Proper linking (https://docs.mapbox.com/help/getting-started/attribution/))
         Attribution {
-            text: "© Mapbox, © OpenStreetMap",
-            url: "https://www.mapbox.com/about/maps/",
+            text: "© Mapbox, © OpenStreetMap".into(),
+            url: "https://www.mapbox.com/about/maps/".into(),
             logo_light: Some(egui::include_image!("../assets/mapbox-logo-white.svg")),
             logo_dark: Some(egui::include_image!("../assets/mapbox-logo-black.svg")),
         }
@@ -140,3 +178,409 @@ impl TileSource for Mapbox {
         512
     }
 }
+
+/// Tile source configured with a Leaflet-style URL template, such as
+/// `https://{s}.tile.openstreetmap.org/{z}/{x}/{y}.png`. Supported placeholders are:
+///
+/// * `{z}`, `{x}` and `{y}` - tile coordinates,
+/// * `{-y}` - Y coordinate in [`TileScheme::Tms`],
+/// * `{s}` - one of the [`UrlTemplate::subdomains`], rotated between tiles,
+/// * `{r}` - `@2x` if [`UrlTemplate::retina`] is set, nothing otherwise,
+/// * `{quadkey}` - see [`TileId::quadkey`].
+///
+/// # Examples
+///
+/// ```
+/// use walkers::providers::{Attribution, UrlTemplate};
+///
+/// let source = UrlTemplate::new(
+///     "https://{s}.tile.opentopomap.org/{z}/{x}/{y}.png",
+///     Attribution::new("OpenTopoMap (CC-BY-SA)", "https://opentopomap.org"),
+/// );
+/// ```
+pub struct UrlTemplate {
+    pub template: String,
+
+    /// Values of `{s}`. Each tile always gets the same one, so it can be cached by the server.
+    pub subdomains: Vec<String>,
+
+    /// Whether `{r}` should request high resolution tiles. Note that it does not change
+    /// [`UrlTemplate::tile_size`].
+    pub retina: bool,
+
+    pub attribution: Attribution,
+
+    /// Size of each tile, should be a multiple of 256
+    pub tile_size: u32,
+}
+
+impl UrlTemplate {
+    /// Template with `a`, `b` and `c` subdomains and 256px tiles.
+    pub fn new(template: impl Into<String>, attribution: Attribution) -> Self {
+        Self {
+            template: template.into(),
+            subdomains: ["a", "b", "c"].map(str::to_owned).to_vec(),
+            retina: false,
+            attribution,
+            tile_size: 256,
+        }
+    }
+}
+
+impl TileSource for UrlTemplate {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        let mut url = self
+            .template
+            .replace("{z}", &tile_id.zoom.to_string())
+            .replace("{x}", &tile_id.x.to_string())
+            .replace("{y}", &tile_id.y.to_string())
+            .replace("{r}", if self.retina { "@2x" } else { "" })
+            .replace("{quadkey}", &tile_id.quadkey());
+
+        if let Some(y) = TileScheme::Tms.y(tile_id) {
+            url = url.replace("{-y}", &y.to_string());
+        }
+
+        if !self.subdomains.is_empty() {
+            let index = (tile_id.x as usize + tile_id.y as usize) % self.subdomains.len();
+            url = url.replace("{s}", &self.subdomains[index]);
+        }
+
+        url
+    }
+
+    fn attribution(&self) -> Attribution {
+        self.attribution.clone()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+}
+
+/// Version of the WMS protocol, which decides how some of the GetMap parameters are named.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WmsVersion {
+    V1_1_1,
+    #[default]
+    V1_3_0,
+}
+
+/// Tile source rendering each tile with a [WMS](https://www.ogc.org/standard/wms/) GetMap
+/// request, in the Web Mercator (EPSG:3857) projection. The server must support this projection.
+///
+/// # Examples
+///
+/// ```
+/// use walkers::providers::{Attribution, Wms};
+///
+/// let source = Wms::new(
+///     "https://example.com/wms",
+///     ["roads", "rivers"],
+///     Attribution::new("Someone", "https://example.com"),
+/// );
+/// ```
+pub struct Wms {
+    /// Address of the service, possibly with its own query parameters.
+    pub url: String,
+
+    pub version: WmsVersion,
+
+    /// Layers drawn on top of each other, first one at the bottom.
+    pub layers: Vec<String>,
+
+    /// Style of each of the [`Wms::layers`]. Empty means server's default for all of them.
+    pub styles: Vec<String>,
+
+    /// MIME type of the images, such as `image/png` or `image/jpeg`.
+    pub format: String,
+
+    /// Whether areas without data should be transparent, which is useful for overlays.
+    pub transparent: bool,
+
+    pub attribution: Attribution,
+
+    /// Size of each tile, should be a multiple of 256
+    pub tile_size: u32,
+}
+
+impl Wms {
+    /// Version 1.3.0 service with transparent PNG tiles, drawn with default styles.
+    pub fn new(
+        url: impl Into<String>,
+        layers: impl IntoIterator<Item = impl Into<String>>,
+        attribution: Attribution,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            version: WmsVersion::default(),
+            layers: layers.into_iter().map(Into::into).collect(),
+            styles: Vec::new(),
+            format: "image/png".to_owned(),
+            transparent: true,
+            attribution,
+            tile_size: 256,
+        }
+    }
+}
+
+impl TileSource for Wms {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        let (version, crs) = match self.version {
+            WmsVersion::V1_1_1 => ("1.1.1", "SRS"),
+            WmsVersion::V1_3_0 => ("1.3.0", "CRS"),
+        };
+
+        let Ok(mut url) = reqwest::Url::parse(&self.url) else {
+            // Request is going to fail anyway, with an error saying why.
+            return self.url.clone();
+        };
+
+        // EPSG:3857 has easting first in both versions, so the axis order does not change.
+        let [min_x, min_y, max_x, max_y] = tile_id.web_mercator_bounds();
+        let tile_size = self.tile_size.to_string();
+
+        url.query_pairs_mut()
+            .append_pair("SERVICE", "WMS")
+            .append_pair("REQUEST", "GetMap")
+            .append_pair("VERSION", version)
+            .append_pair("LAYERS", &self.layers.join(","))
+            .append_pair("STYLES", &self.styles.join(","))
+            .append_pair("FORMAT", &self.format)
+            .append_pair(
+                "TRANSPARENT",
+                if self.transparent { "TRUE" } else { "FALSE" },
+            )
+            .append_pair(crs, "EPSG:3857")
+            .append_pair("BBOX", &format!("{},{},{},{}", min_x, min_y, max_x, max_y))
+            .append_pair("WIDTH", &tile_size)
+            .append_pair("HEIGHT", &tile_size);
+
+        url.into()
+    }
+
+    fn attribution(&self) -> Attribution {
+        self.attribution.clone()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+}
+
+/// Direction of the Y axis in tile coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TileScheme {
+    /// Y goes from the north, like in OpenStreetMap and Google Maps.
+    #[default]
+    Xyz,
+
+    /// Y goes from the south, as in the
+    /// [Tile Map Service](https://wiki.osgeo.org/wiki/Tile_Map_Service_Specification).
+    Tms,
+}
+
+impl TileScheme {
+    /// Y coordinate of the tile in this scheme, or `None` if the tile is outside of the world.
+    pub(crate) fn y(&self, tile_id: TileId) -> Option<u32> {
+        match self {
+            Self::Xyz => Some(tile_id.y),
+            Self::Tms => (2u32.pow(tile_id.zoom as u32) - 1).checked_sub(tile_id.y),
+        }
+    }
+}
+
+/// Tiles stored in a directory tree on disk, as `{z}/{x}/{y}.{extension}`. This is what
+/// `gdal2tiles` and similar tools produce. Pass it to [`crate::Tiles::with_fetcher`].
+pub struct LocalDirectory {
+    pub path: PathBuf,
+
+    /// File extension, without the dot.
+    pub extension: String,
+
+    /// `gdal2tiles` uses [`TileScheme::Tms`], unless run with `--xyz`.
+    pub scheme: TileScheme,
+
+    pub attribution: Attribution,
+
+    /// Size of each tile, should be a multiple of 256
+    pub tile_size: u32,
+}
+
+impl LocalDirectory {
+    /// PNG tiles in [`TileScheme::Xyz`], with no attribution.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            extension: "png".to_owned(),
+            scheme: TileScheme::Xyz,
+            attribution: Attribution::default(),
+            tile_size: 256,
+        }
+    }
+
+    fn tile_path(&self, tile_id: TileId) -> Option<PathBuf> {
+        Some(
+            self.path
+                .join(tile_id.zoom.to_string())
+                .join(tile_id.x.to_string())
+                .join(format!("{}.{}", self.scheme.y(tile_id)?, self.extension)),
+        )
+    }
+}
+
+impl Fetcher for LocalDirectory {
+    fn fetch(&self, tile_id: TileId) -> BoxFuture<'_, Result<TileData, FetchError>> {
+        let path = self.tile_path(tile_id);
+
+        Box::pin(async move {
+            let Some(path) = path else {
+                return Err(FetchError::Permanent(
+                    format!("{:?} is outside of the world", tile_id).into(),
+                ));
+            };
+
+            // Reading the disk would block the IO thread.
+            in_background(move || std::fs::read(path))
+                .await
+                .map(TileData::Bytes)
+                .map_err(|e| FetchError::Permanent(e.into()))
+        })
+    }
+
+    fn attribution(&self) -> Attribution {
+        self.attribution.clone()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::block_on;
+
+    fn attribution() -> Attribution {
+        Attribution::default()
+    }
+
+    #[test]
+    fn url_template_placeholders() {
+        let tile_id = TileId {
+            x: 3,
+            y: 5,
+            zoom: 3,
+        };
+
+        let source = UrlTemplate::new("https://{s}.example.com/{z}/{x}/{y}{r}.png", attribution());
+        assert_eq!("https://c.example.com/3/3/5.png", source.tile_url(tile_id));
+
+        let source = UrlTemplate {
+            retina: true,
+            subdomains: vec!["one".to_owned(), "two".to_owned()],
+            ..UrlTemplate::new("https://{s}.example.com/{z}/{x}/{-y}{r}.png", attribution())
+        };
+        assert_eq!(
+            "https://one.example.com/3/3/2@2x.png",
+            source.tile_url(tile_id)
+        );
+
+        let source = UrlTemplate::new("https://example.com/tiles/{quadkey}.jpeg", attribution());
+        assert_eq!(
+            "https://example.com/tiles/213.jpeg",
+            source.tile_url(tile_id)
+        );
+    }
+
+    #[test]
+    fn url_template_with_runtime_attribution() {
+        let name = String::from("Someone");
+        let source = UrlTemplate::new(
+            "https://example.com/{z}/{x}/{y}.png",
+            Attribution::new(name, format!("https://{}.example.com", "someone")),
+        );
+
+        assert_eq!("Someone", source.attribution().text);
+        assert_eq!("https://someone.example.com", source.attribution().url);
+    }
+
+    #[test]
+    fn wms_get_map_request() {
+        let tile_id = TileId {
+            x: 1,
+            y: 0,
+            zoom: 1,
+        };
+
+        let source = Wms::new(
+            "https://example.com/wms",
+            ["roads", "rivers"],
+            attribution(),
+        );
+        assert_eq!(
+            "https://example.com/wms?SERVICE=WMS&REQUEST=GetMap&VERSION=1.3.0\
+            &LAYERS=roads%2Crivers&STYLES=&FORMAT=image%2Fpng&TRANSPARENT=TRUE&CRS=EPSG%3A3857\
+            &BBOX=0%2C0%2C20037508.342789244%2C20037508.342789244&WIDTH=256&HEIGHT=256",
+            source.tile_url(tile_id)
+        );
+
+        let source = Wms {
+            version: WmsVersion::V1_1_1,
+            styles: vec!["default".to_owned()],
+            format: "image/jpeg".to_owned(),
+            transparent: false,
+            ..Wms::new("https://example.com/wms?map=test", ["roads"], attribution())
+        };
+        assert_eq!(
+            "https://example.com/wms?map=test&SERVICE=WMS&REQUEST=GetMap&VERSION=1.1.1\
+            &LAYERS=roads&STYLES=default&FORMAT=image%2Fjpeg&TRANSPARENT=FALSE&SRS=EPSG%3A3857\
+            &BBOX=0%2C0%2C20037508.342789244%2C20037508.342789244&WIDTH=256&HEIGHT=256",
+            source.tile_url(tile_id)
+        );
+
+        // Names can not break the query.
+        let source = Wms::new("https://example.com/wms", ["a&b=c"], attribution());
+        assert!(source.tile_url(tile_id).contains("&LAYERS=a%26b%3Dc&"));
+    }
+
+    fn tile_directory() -> tempfile::TempDir {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(directory.path().join("1/0")).unwrap();
+        std::fs::write(directory.path().join("1/0/0.jpg"), b"image").unwrap();
+        directory
+    }
+
+    fn fetch(source: &LocalDirectory, x: u32, y: u32, zoom: u8) -> Option<Vec<u8>> {
+        match block_on(source.fetch(TileId { x, y, zoom })) {
+            Ok(TileData::Bytes(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn tiles_are_read_from_directory() {
+        let directory = tile_directory();
+        let source = LocalDirectory {
+            extension: "jpg".to_owned(),
+            ..LocalDirectory::new(directory.path())
+        };
+
+        assert_eq!(Some(b"image".to_vec()), fetch(&source, 0, 0, 1));
+        assert_eq!(None, fetch(&source, 0, 1, 1));
+    }
+
+    #[test]
+    fn tms_y_axis_is_flipped() {
+        let directory = tile_directory();
+        let source = LocalDirectory {
+            extension: "jpg".to_owned(),
+            scheme: TileScheme::Tms,
+            ..LocalDirectory::new(directory.path())
+        };
+
+        assert_eq!(Some(b"image".to_vec()), fetch(&source, 0, 1, 1));
+        assert_eq!(None, fetch(&source, 0, 0, 1));
+    }
+}
@@ -0,0 +1,272 @@
+//! Queue of tiles waiting to be downloaded, shared between [`crate::Tiles`] and the IO thread.
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+};
+
+use futures::{task::AtomicWaker, Stream};
+
+use crate::{freshness::Freshness, mercator::TileId};
+
+/// How urgently the tile is needed. Lower value means more urgent.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Priority {
+    /// Tile is visible. The closer to the center of the map, the more urgent.
+    Visible { distance: f32 },
+
+    /// Tile is not visible yet, but it soon might be, e.g. when the map gets dragged.
+    Prefetch { distance: f32 },
+}
+
+/// Visible tiles go first, then distances are compared like [`f32::total_cmp`] does, so there is
+/// an order even if some distance is NaN.
+impl Ord for Priority {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Visible { distance: a }, Self::Visible { distance: b })
+            | (Self::Prefetch { distance: a }, Self::Prefetch { distance: b }) => a.total_cmp(b),
+            (Self::Visible { .. }, Self::Prefetch { .. }) => Ordering::Less,
+            (Self::Prefetch { .. }, Self::Visible { .. }) => Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for Priority {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Priority {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Priority {}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self::Visible { distance: 0. }
+    }
+}
+
+struct Request {
+    priority: Priority,
+
+    /// Number of the egui frame in which this tile was requested.
+    frame: u64,
+
+    /// Present if the tile is already known, but expired and needs to be revalidated.
+    stale: Option<Freshness>,
+}
+
+#[derive(Default)]
+struct State {
+    waiting: HashMap<TileId, Request>,
+
+    /// Tiles taken by the IO thread, for which the result was not received yet.
+    in_flight: HashSet<TileId>,
+
+    /// The most recent frame in which something was requested.
+    latest_frame: u64,
+
+    closed: bool,
+}
+
+impl State {
+    /// Take the most urgent request, dropping these which are no longer needed.
+    fn pop(&mut self) -> Option<(TileId, Option<Freshness>)> {
+        // Requests are repeated each frame for as long as the tile is needed. If it was not,
+        // the tile is not visible anymore, and there is no point in downloading it.
+        let latest_frame = self.latest_frame;
+        self.waiting
+            .retain(|_, request| request.frame >= latest_frame);
+
+        let tile_id = self
+            .waiting
+            .iter()
+            .min_by_key(|(_, request)| request.priority)
+            .map(|(tile_id, _)| *tile_id)?;
+
+        let request = self.waiting.remove(&tile_id)?;
+        self.in_flight.insert(tile_id);
+        Some((tile_id, request.stale))
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    waker: AtomicWaker,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        // State is always consistent, even if some thread panicked while holding the lock.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Producer side of the queue. Dropping it ends the [`RequestQueue::stream`].
+#[derive(Default)]
+pub(crate) struct RequestQueue(Arc<Shared>);
+
+impl RequestQueue {
+    /// Request the tile in the given frame. Requests have to be repeated in each frame,
+    /// otherwise they are dropped. `stale` should be given for expired tiles, which need to be
+    /// revalidated.
+    pub fn push(&self, tile_id: TileId, priority: Priority, frame: u64, stale: Option<Freshness>) {
+        {
+            let mut state = self.0.state();
+
+            if state.in_flight.contains(&tile_id) {
+                return;
+            }
+
+            state.latest_frame = state.latest_frame.max(frame);
+
+            match state.waiting.entry(tile_id) {
+                Entry::Occupied(mut entry) => {
+                    let request = entry.get_mut();
+                    if request.frame < frame || priority < request.priority {
+                        request.priority = priority;
+                    }
+                    request.frame = request.frame.max(frame);
+                    request.stale = stale;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Request {
+                        priority,
+                        frame,
+                        stale,
+                    });
+                }
+            }
+        }
+
+        self.0.waker.wake();
+    }
+
+    /// Mark the tile as no longer being downloaded, so it can be requested again.
+    pub fn done(&self, tile_id: TileId) {
+        self.0.state().in_flight.remove(&tile_id);
+    }
+
+    /// Stream of tiles to be downloaded, the most urgent first. It is meant to be polled only
+    /// when there is capacity for another download.
+    pub fn stream(&self) -> RequestStream {
+        RequestStream(self.0.clone())
+    }
+}
+
+/// Consumer side of the queue, see [`RequestQueue::stream`].
+pub(crate) struct RequestStream(Arc<Shared>);
+
+impl Stream for RequestStream {
+    type Item = (TileId, Option<Freshness>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Register first, so a push happening in the meantime is not missed.
+        self.0.waker.register(cx.waker());
+
+        let mut state = self.0.state();
+        match state.pop() {
+            Some(request) => Poll::Ready(Some(request)),
+            None if state.closed => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for RequestQueue {
+    fn drop(&mut self) {
+        self.0.state().closed = true;
+        self.0.waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn tile(x: u32) -> TileId {
+        TileId { x, y: 0, zoom: 1 }
+    }
+
+    fn pop(queue: &RequestQueue) -> Option<TileId> {
+        queue.0.state().pop().map(|(tile_id, _)| tile_id)
+    }
+
+    #[test]
+    fn priorities_are_totally_ordered() {
+        let visible = |distance| Priority::Visible { distance };
+        let prefetch = |distance| Priority::Prefetch { distance };
+
+        assert!(visible(100.) < visible(200.));
+        assert!(visible(f32::INFINITY) < prefetch(0.));
+        assert!(visible(1.) < visible(f32::NAN));
+        assert_eq!(visible(f32::NAN), visible(f32::NAN));
+    }
+
+    #[test]
+    fn visible_tiles_closest_to_the_center_go_first() {
+        let queue = RequestQueue::default();
+
+        queue.push(tile(0), Priority::Prefetch { distance: 0. }, 1, None);
+        queue.push(tile(1), Priority::Visible { distance: 200. }, 1, None);
+        queue.push(tile(2), Priority::Visible { distance: 100. }, 1, None);
+
+        assert_eq!(Some(tile(2)), pop(&queue));
+        assert_eq!(Some(tile(1)), pop(&queue));
+        assert_eq!(Some(tile(0)), pop(&queue));
+        assert_eq!(None, pop(&queue));
+    }
+
+    #[test]
+    fn requests_not_repeated_in_the_latest_frame_are_dropped() {
+        let queue = RequestQueue::default();
+
+        queue.push(tile(0), Priority::default(), 1, None);
+        queue.push(tile(1), Priority::default(), 1, None);
+        queue.push(tile(1), Priority::default(), 2, None);
+
+        assert_eq!(Some(tile(1)), pop(&queue));
+        assert_eq!(None, pop(&queue));
+    }
+
+    #[test]
+    fn tiles_in_flight_are_not_requested_again() {
+        let queue = RequestQueue::default();
+
+        queue.push(tile(0), Priority::default(), 1, None);
+        assert_eq!(Some(tile(0)), pop(&queue));
+
+        queue.push(tile(0), Priority::default(), 2, None);
+        assert_eq!(None, pop(&queue));
+
+        queue.done(tile(0));
+        queue.push(tile(0), Priority::default(), 3, None);
+        assert_eq!(Some(tile(0)), pop(&queue));
+    }
+
+    #[test]
+    fn stream_ends_when_queue_is_dropped() {
+        let queue = RequestQueue::default();
+        let mut stream = Box::pin(queue.stream());
+
+        queue.push(tile(0), Priority::default(), 1, None);
+        drop(queue);
+
+        futures::executor::block_on(async {
+            assert_eq!(Some((tile(0), None)), stream.next().await);
+            assert_eq!(None, stream.next().await);
+        });
+    }
+}
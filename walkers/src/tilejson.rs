@@ -0,0 +1,257 @@
+//! Tile sources described by [TileJSON](https://github.com/mapbox/tilejson-spec) documents,
+//! versions 2.x and 3.x.
+use std::{ops::RangeInclusive, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    download::{download_document, HttpOptions},
+    fetcher::FetchError,
+    mercator::{TileId, TILE_SIZE},
+    providers::{Attribution, TileScheme, TileSource},
+    Position,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(std::io::Error),
+
+    #[error(transparent)]
+    Http(reqwest::Error),
+
+    #[error(transparent)]
+    Json(serde_json::Error),
+
+    #[error(transparent)]
+    Credentials(FetchError),
+
+    #[error("TileJSON document does not contain any tile URL")]
+    NoTiles,
+}
+
+/// Fields of the document which are used by Walkers. Everything else is ignored.
+#[derive(Deserialize)]
+struct Document {
+    tiles: Vec<String>,
+    name: Option<String>,
+    attribution: Option<String>,
+    scheme: Option<String>,
+    minzoom: Option<u8>,
+    maxzoom: Option<u8>,
+    bounds: Option<[f64; 4]>,
+
+    /// Not a part of the specification, but used by some servers, e.g. TileServer GL.
+    #[serde(rename = "tileSize")]
+    tile_size: Option<u32>,
+}
+
+/// Tile source read from a TileJSON document. Pass it to [`crate::Tiles::new`].
+///
+/// # Examples
+///
+/// ```
+/// use walkers::tilejson::TileJson;
+///
+/// let source = TileJson::parse(
+///     r#"{
+///         "tilejson": "3.0.0",
+///         "tiles": ["https://tiles.example.com/{z}/{x}/{y}.png"],
+///         "maxzoom": 14
+///     }"#,
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileJson {
+    /// URL templates with `{z}`, `{x}` and `{y}` placeholders. If there are more than one,
+    /// tiles are spread between them.
+    pub tiles: Vec<String>,
+
+    pub name: Option<String>,
+
+    /// Attribution of the tiles, which might contain HTML.
+    pub attribution: Option<String>,
+
+    pub scheme: TileScheme,
+
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+
+    /// South-west and north-east corners of the area covered by the tiles.
+    pub bounds: Option<(Position, Position)>,
+
+    /// Size of each tile, should be a multiple of 256
+    pub tile_size: u32,
+}
+
+impl TileJson {
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let document: Document = serde_json::from_str(json).map_err(Error::Json)?;
+
+        if document.tiles.is_empty() {
+            return Err(Error::NoTiles);
+        }
+
+        Ok(Self {
+            tiles: document.tiles,
+            name: document.name,
+            attribution: document.attribution,
+            scheme: match document.scheme.as_deref() {
+                Some("tms") => TileScheme::Tms,
+                _ => TileScheme::Xyz,
+            },
+            min_zoom: document.minzoom.unwrap_or(0),
+            max_zoom: document.maxzoom.unwrap_or(30),
+            bounds: document.bounds.map(|[west, south, east, north]| {
+                (
+                    Position::from_lon_lat(west, south),
+                    Position::from_lon_lat(east, north),
+                )
+            }),
+            tile_size: document.tile_size.unwrap_or(TILE_SIZE),
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path).map_err(Error::Io)?)
+    }
+
+    /// Download the document, with the client and credentials given by the options. Relative
+    /// tile URLs are not supported.
+    pub async fn from_url(url: &str, http_options: &HttpOptions) -> Result<Self, Error> {
+        let json = download_document(url, http_options, Error::Http, Error::Credentials).await?;
+        Self::parse(&json)
+    }
+}
+
+impl TileSource for TileJson {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        let index = (tile_id.x as usize + tile_id.y as usize) % self.tiles.len();
+        let y = self.scheme.y(tile_id).unwrap_or(tile_id.y);
+
+        self.tiles[index]
+            .replace("{z}", &tile_id.zoom.to_string())
+            .replace("{x}", &tile_id.x.to_string())
+            .replace("{y}", &y.to_string())
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: self.attribution.clone().unwrap_or_default().into(),
+            ..Default::default()
+        }
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        self.min_zoom..=self.max_zoom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::block_on;
+
+    const DOCUMENT: &str = r#"{
+        "tilejson": "2.2.0",
+        "name": "Test",
+        "attribution": "Someone",
+        "scheme": "tms",
+        "tiles": [
+            "https://a.example.com/{z}/{x}/{y}.png",
+            "https://b.example.com/{z}/{x}/{y}.png"
+        ],
+        "minzoom": 2,
+        "maxzoom": 12,
+        "bounds": [16.8, 50.9, 17.3, 51.3],
+        "tileSize": 512
+    }"#;
+
+    #[test]
+    fn document_is_parsed() {
+        let source = TileJson::parse(DOCUMENT).unwrap();
+
+        assert_eq!(Some("Test"), source.name.as_deref());
+        assert_eq!("Someone", source.attribution().text);
+        assert_eq!(2..=12, source.zoom_range());
+        assert_eq!(512, source.tile_size());
+        assert_eq!(
+            Some((
+                Position::from_lon_lat(16.8, 50.9),
+                Position::from_lon_lat(17.3, 51.3)
+            )),
+            source.bounds
+        );
+
+        let tile_url = |x, y, zoom| source.tile_url(TileId { x, y, zoom });
+        assert_eq!("https://a.example.com/2/0/3.png", tile_url(0, 0, 2));
+        assert_eq!("https://b.example.com/2/1/3.png", tile_url(1, 0, 2));
+    }
+
+    #[test]
+    fn defaults_are_used_for_missing_fields() {
+        let source =
+            TileJson::parse(r#"{"tiles": ["https://example.com/{z}/{x}/{y}.png"]}"#).unwrap();
+
+        assert_eq!(TileScheme::Xyz, source.scheme);
+        assert_eq!(0..=30, source.zoom_range());
+        assert_eq!(256, source.tile_size());
+        assert_eq!("", source.attribution().text);
+    }
+
+    #[test]
+    fn document_without_tiles_is_rejected() {
+        assert!(matches!(
+            TileJson::parse(r#"{"tiles": []}"#),
+            Err(Error::NoTiles)
+        ));
+        assert!(matches!(TileJson::parse("{}"), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn document_is_downloaded() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("GET", "/tiles.json")
+            .with_body(DOCUMENT)
+            .create();
+
+        let source = block_on(TileJson::from_url(
+            &format!("{}/tiles.json", server.url()),
+            &HttpOptions::default(),
+        ))
+        .unwrap();
+
+        assert_eq!(Some("Test"), source.name.as_deref());
+    }
+
+    #[test]
+    fn document_is_downloaded_with_credentials() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("GET", "/tiles.json")
+            .match_header("authorization", "Basic dXNlcjpzZWNyZXQ=")
+            .with_body(DOCUMENT)
+            .create();
+
+        let http_options = HttpOptions {
+            credentials: Some(std::sync::Arc::new(crate::BasicAuth {
+                username: "user".to_owned(),
+                password: Some("secret".to_owned()),
+            })),
+            ..Default::default()
+        };
+        let source = block_on(TileJson::from_url(
+            &format!("{}/tiles.json", server.url()),
+            &http_options,
+        ))
+        .unwrap();
+
+        assert_eq!(Some("Test"), source.name.as_deref());
+    }
+}
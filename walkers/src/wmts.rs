@@ -0,0 +1,573 @@
+//! Tile sources configured from a [WMTS](https://www.ogc.org/standard/wmts/) GetCapabilities
+//! document, so only the layer needs to be picked.
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use roxmltree::Node;
+
+use crate::{
+    download::{download_document, HttpOptions},
+    fetcher::FetchError,
+    mercator::TileId,
+    providers::{Attribution, TileSource},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Http(reqwest::Error),
+
+    #[error(transparent)]
+    Xml(roxmltree::Error),
+
+    #[error(transparent)]
+    Credentials(FetchError),
+
+    #[error("there is no layer '{0}'")]
+    NoSuchLayer(String),
+
+    #[error("layer '{0}' has no tile matrix set compatible with Web Mercator")]
+    NoWebMercator(String),
+
+    #[error("layer '{0}' has neither resource URL, nor there is a KVP GetTile URL")]
+    NoTileUrl(String),
+}
+
+/// Half of the Earth's circumference at the equator, which is where Web Mercator world ends.
+const HALF_WORLD: f64 = 20_037_508.342_789_244;
+
+/// Size of a pixel assumed by WMTS scale denominators, in meters.
+const PIXEL_SIZE: f64 = 0.00028;
+
+/// Contents of the GetCapabilities document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub layers: Vec<Layer>,
+    pub tile_matrix_sets: Vec<TileMatrixSet>,
+
+    /// Base URL of GetTile requests using key-value pairs, if the server supports them.
+    pub get_tile_url: Option<String>,
+
+    pub provider_name: Option<String>,
+    pub provider_site: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub identifier: String,
+    pub title: Option<String>,
+
+    /// Identifiers of the styles, default one first.
+    pub styles: Vec<String>,
+
+    /// MIME types of the images, such as `image/png`.
+    pub formats: Vec<String>,
+
+    /// Identifiers of the [`TileMatrixSet`]s in which the layer is available.
+    pub tile_matrix_sets: Vec<String>,
+
+    /// Templates of RESTful tile URLs.
+    pub resource_urls: Vec<ResourceUrl>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceUrl {
+    pub format: String,
+
+    /// URL with `{TileMatrixSet}`, `{TileMatrix}`, `{TileRow}`, `{TileCol}` and `{Style}`
+    /// placeholders.
+    pub template: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrixSet {
+    pub identifier: String,
+
+    /// Coordinate reference system, such as `urn:ogc:def:crs:EPSG::3857`.
+    pub crs: String,
+
+    pub tile_matrices: Vec<TileMatrix>,
+}
+
+/// Grid of tiles at a single scale, which corresponds to a zoom level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrix {
+    pub identifier: String,
+    pub scale_denominator: f64,
+
+    /// In the units of the [`TileMatrixSet::crs`].
+    pub top_left_corner: (f64, f64),
+
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub matrix_width: u32,
+    pub matrix_height: u32,
+}
+
+impl Capabilities {
+    pub fn parse(xml: &str) -> Result<Self, Error> {
+        let document = roxmltree::Document::parse(xml).map_err(Error::Xml)?;
+        let root = document.root_element();
+
+        let contents = child(root, "Contents");
+        let provider = child(root, "ServiceProvider");
+
+        Ok(Self {
+            layers: contents
+                .into_iter()
+                .flat_map(|contents| children(contents, "Layer"))
+                .map(parse_layer)
+                .collect(),
+            tile_matrix_sets: contents
+                .into_iter()
+                .flat_map(|contents| children(contents, "TileMatrixSet"))
+                .map(parse_tile_matrix_set)
+                .collect(),
+            get_tile_url: child(root, "OperationsMetadata").and_then(parse_get_tile_url),
+            provider_name: provider.and_then(|provider| text(provider, "ProviderName")),
+            provider_site: provider
+                .and_then(|provider| child(provider, "ProviderSite"))
+                .and_then(href),
+        })
+    }
+
+    /// Download and parse the document, with the client and credentials given by the options.
+    pub async fn from_url(url: &str, http_options: &HttpOptions) -> Result<Self, Error> {
+        let xml = download_document(url, http_options, Error::Http, Error::Credentials).await?;
+        Self::parse(&xml)
+    }
+
+    /// Tile source showing the layer in its default style. PNG is preferred over JPEG, and
+    /// RESTful URLs over key-value pairs.
+    pub fn source(&self, layer: &str) -> Result<Wmts, Error> {
+        let layer = self
+            .layers
+            .iter()
+            .find(|candidate| candidate.identifier == layer)
+            .ok_or_else(|| Error::NoSuchLayer(layer.to_owned()))?;
+
+        let (tile_matrix_set, tile_matrices) = layer
+            .tile_matrix_sets
+            .iter()
+            .filter_map(|identifier| {
+                self.tile_matrix_sets
+                    .iter()
+                    .find(|set| set.identifier == *identifier)
+            })
+            .map(|set| (set, set.web_mercator_matrices()))
+            .find(|(_, matrices)| !matrices.is_empty())
+            .ok_or_else(|| Error::NoWebMercator(layer.identifier.clone()))?;
+
+        let formats: Vec<&str> = if layer.resource_urls.is_empty() {
+            layer.formats.iter().map(String::as_str).collect()
+        } else {
+            layer
+                .resource_urls
+                .iter()
+                .map(|url| url.format.as_str())
+                .collect()
+        };
+        let format = ["image/png", "image/jpeg"]
+            .into_iter()
+            .find(|preferred| formats.contains(preferred))
+            .or_else(|| formats.first().copied())
+            .unwrap_or("image/png");
+
+        let endpoint = match layer.resource_urls.iter().find(|url| url.format == format) {
+            Some(url) => Endpoint::Rest(url.template.clone()),
+            None => Endpoint::Kvp(
+                self.get_tile_url
+                    .clone()
+                    .ok_or_else(|| Error::NoTileUrl(layer.identifier.clone()))?,
+            ),
+        };
+
+        let tile_size = tile_matrices
+            .values()
+            .next()
+            .map_or(256, |matrix| matrix.tile_width);
+
+        Ok(Wmts {
+            layer: layer.identifier.clone(),
+            style: layer.styles.first().cloned().unwrap_or_default(),
+            format: format.to_owned(),
+            tile_matrix_set: tile_matrix_set.identifier.clone(),
+            tile_matrices: tile_matrices
+                .into_iter()
+                .map(|(zoom, matrix)| (zoom, matrix.identifier.clone()))
+                .collect(),
+            endpoint,
+            tile_size,
+            attribution: Attribution {
+                text: self.provider_name.clone().unwrap_or_default().into(),
+                url: self.provider_site.clone().unwrap_or_default().into(),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+impl TileMatrixSet {
+    /// Matrices which are laid out exactly like OpenStreetMap-like tiles, by their zoom level.
+    /// Empty if the set uses a different projection.
+    fn web_mercator_matrices(&self) -> BTreeMap<u8, &TileMatrix> {
+        // Such as `EPSG:3857` or `urn:ogc:def:crs:EPSG::3857`. 900913 is its unofficial code.
+        let web_mercator = self
+            .crs
+            .rsplit(':')
+            .next()
+            .is_some_and(|code| code == "3857" || code == "900913");
+
+        if !web_mercator {
+            return BTreeMap::new();
+        }
+
+        self.tile_matrices
+            .iter()
+            .filter_map(|matrix| Some((matrix.web_mercator_zoom()?, matrix)))
+            .collect()
+    }
+}
+
+impl TileMatrix {
+    /// Zoom level of the matrix, if it covers the whole world with a power of two square tiles.
+    fn web_mercator_zoom(&self) -> Option<u8> {
+        let (left, top) = self.top_left_corner;
+        let width =
+            self.scale_denominator * PIXEL_SIZE * self.tile_width as f64 * self.matrix_width as f64;
+
+        let aligned = self.matrix_width.is_power_of_two()
+            && self.matrix_width == self.matrix_height
+            && self.tile_width == self.tile_height
+            && (left + HALF_WORLD).abs() < 1.
+            && (top - HALF_WORLD).abs() < 1.
+            && ((width - 2. * HALF_WORLD) / (2. * HALF_WORLD)).abs() < 1e-3;
+
+        aligned.then(|| self.matrix_width.ilog2() as u8)
+    }
+}
+
+/// How tile URLs are built.
+#[derive(Debug, Clone, PartialEq)]
+enum Endpoint {
+    /// From a [`ResourceUrl`] template.
+    Rest(String),
+
+    /// From key-value pairs appended to this URL.
+    Kvp(String),
+}
+
+/// Layer of a WMTS service. Created by [`Capabilities::source`], pass it to
+/// [`crate::Tiles::new`].
+#[derive(Clone)]
+pub struct Wmts {
+    layer: String,
+
+    /// One of the [`Layer::styles`].
+    pub style: String,
+
+    format: String,
+    tile_matrix_set: String,
+
+    /// Identifiers of the tile matrices by zoom level.
+    tile_matrices: BTreeMap<u8, String>,
+
+    endpoint: Endpoint,
+    tile_size: u32,
+
+    /// Taken from the service provider, if present.
+    pub attribution: Attribution,
+}
+
+impl Wmts {
+    /// URL of the tile, or `None` if there is no tile matrix for its zoom.
+    fn url(&self, tile_id: TileId) -> Option<String> {
+        let tile_matrix = self.tile_matrices.get(&tile_id.zoom)?;
+
+        Some(match &self.endpoint {
+            Endpoint::Rest(template) => template
+                .replace("{TileMatrixSet}", &self.tile_matrix_set)
+                .replace("{TileMatrix}", tile_matrix)
+                .replace("{TileRow}", &tile_id.y.to_string())
+                .replace("{TileCol}", &tile_id.x.to_string())
+                .replace("{Style}", &self.style),
+            Endpoint::Kvp(url) => {
+                let Ok(mut url) = reqwest::Url::parse(url) else {
+                    // Downloading it fails anyway, which is reported along with the reason.
+                    return Some(url.clone());
+                };
+
+                url.query_pairs_mut()
+                    .append_pair("SERVICE", "WMTS")
+                    .append_pair("REQUEST", "GetTile")
+                    .append_pair("VERSION", "1.0.0")
+                    .append_pair("LAYER", &self.layer)
+                    .append_pair("STYLE", &self.style)
+                    .append_pair("FORMAT", &self.format)
+                    .append_pair("TILEMATRIXSET", &self.tile_matrix_set)
+                    .append_pair("TILEMATRIX", tile_matrix)
+                    .append_pair("TILEROW", &tile_id.y.to_string())
+                    .append_pair("TILECOL", &tile_id.x.to_string());
+
+                url.into()
+            }
+        })
+    }
+}
+
+impl TileSource for Wmts {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        // Zooms without a tile matrix are outside of the `zoom_range`, so they are never
+        // requested. Empty URL fails anyway.
+        self.url(tile_id).unwrap_or_default()
+    }
+
+    fn attribution(&self) -> Attribution {
+        self.attribution.clone()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Zoom levels from the first tile matrix, up to the first missing one.
+    fn zoom_range(&self) -> RangeInclusive<u8> {
+        let first = self.tile_matrices.keys().next().copied().unwrap_or(0);
+        let last = (first..=u8::MAX)
+            .take_while(|zoom| self.tile_matrices.contains_key(zoom))
+            .last()
+            .unwrap_or(first);
+        first..=last
+    }
+}
+
+/// Child elements with given name, ignoring the namespace.
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// Trimmed text of the child element.
+fn text(node: Node, name: &'static str) -> Option<String> {
+    Some(child(node, name)?.text()?.trim().to_owned())
+}
+
+/// Value of the `xlink:href` attribute.
+fn href(node: Node) -> Option<String> {
+    node.attributes()
+        .find(|attribute| attribute.name() == "href")
+        .map(|attribute| attribute.value().to_owned())
+}
+
+fn parse_layer(node: Node) -> Layer {
+    let mut styles: Vec<_> = children(node, "Style")
+        .filter_map(|style| {
+            let default = style.attribute("isDefault") == Some("true");
+            Some((text(style, "Identifier")?, default))
+        })
+        .collect();
+    styles.sort_by_key(|(_, default)| !default);
+
+    Layer {
+        identifier: text(node, "Identifier").unwrap_or_default(),
+        title: text(node, "Title"),
+        styles: styles.into_iter().map(|(style, _)| style).collect(),
+        formats: children(node, "Format")
+            .filter_map(|format| Some(format.text()?.trim().to_owned()))
+            .collect(),
+        tile_matrix_sets: children(node, "TileMatrixSetLink")
+            .filter_map(|link| text(link, "TileMatrixSet"))
+            .collect(),
+        resource_urls: children(node, "ResourceURL")
+            .filter(|url| url.attribute("resourceType") == Some("tile"))
+            .filter_map(|url| {
+                Some(ResourceUrl {
+                    format: url.attribute("format")?.to_owned(),
+                    template: url.attribute("template")?.to_owned(),
+                })
+            })
+            .collect(),
+    }
+}
+
+fn parse_tile_matrix_set(node: Node) -> TileMatrixSet {
+    TileMatrixSet {
+        identifier: text(node, "Identifier").unwrap_or_default(),
+        crs: text(node, "SupportedCRS").unwrap_or_default(),
+        tile_matrices: children(node, "TileMatrix")
+            .filter_map(parse_tile_matrix)
+            .collect(),
+    }
+}
+
+/// `None` if the matrix is incomplete.
+fn parse_tile_matrix(node: Node) -> Option<TileMatrix> {
+    let number = |name| text(node, name)?.parse().ok();
+    let mut top_left_corner = text(node, "TopLeftCorner")?
+        .split_whitespace()
+        .map(|coordinate| coordinate.parse().ok())
+        .collect::<Option<Vec<f64>>>()?
+        .into_iter();
+
+    Some(TileMatrix {
+        identifier: text(node, "Identifier")?,
+        scale_denominator: text(node, "ScaleDenominator")?.parse().ok()?,
+        top_left_corner: (top_left_corner.next()?, top_left_corner.next()?),
+        tile_width: number("TileWidth")?,
+        tile_height: number("TileHeight")?,
+        matrix_width: number("MatrixWidth")?,
+        matrix_height: number("MatrixHeight")?,
+    })
+}
+
+/// URL of the GetTile operation which accepts key-value pairs.
+fn parse_get_tile_url(operations: Node) -> Option<String> {
+    children(operations, "Operation")
+        .filter(|operation| operation.attribute("name") == Some("GetTile"))
+        .flat_map(|operation| children(operation, "DCP"))
+        .flat_map(|dcp| children(dcp, "HTTP"))
+        .flat_map(|http| children(http, "Get"))
+        .find(|get| {
+            // Without constraints, all encodings are allowed.
+            let mut values = get
+                .descendants()
+                .filter(|node| node.is_element() && node.tag_name().name() == "Value");
+            child(*get, "Constraint").is_none()
+                || values.any(|value| value.text().map(str::trim) == Some("KVP"))
+        })
+        .and_then(href)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::block_on;
+
+    const CAPABILITIES: &str = include_str!("../assets/wmts-capabilities.xml");
+
+    static TILE_ID: TileId = TileId {
+        x: 1,
+        y: 2,
+        zoom: 2,
+    };
+
+    #[test]
+    fn capabilities_are_parsed() {
+        let capabilities = Capabilities::parse(CAPABILITIES).unwrap();
+
+        let identifiers: Vec<_> = capabilities
+            .layers
+            .iter()
+            .map(|layer| layer.identifier.as_str())
+            .collect();
+        assert_eq!(vec!["ortho", "roads", "parcels"], identifiers);
+
+        let ortho = &capabilities.layers[0];
+        assert_eq!(Some("Orthophotomap"), ortho.title.as_deref());
+        assert_eq!(vec!["default", "infrared"], ortho.styles);
+        assert_eq!(vec!["image/jpeg", "image/png"], ortho.formats);
+        assert_eq!(2, ortho.resource_urls.len());
+
+        assert_eq!(
+            Some("https://example.com/wmts?"),
+            capabilities.get_tile_url.as_deref()
+        );
+        assert_eq!(Some("Test Provider"), capabilities.provider_name.as_deref());
+
+        let matrices = capabilities.tile_matrix_sets[1].web_mercator_matrices();
+        assert_eq!(vec![0, 1, 2], matrices.keys().copied().collect::<Vec<_>>());
+        assert!(capabilities.tile_matrix_sets[0]
+            .web_mercator_matrices()
+            .is_empty());
+    }
+
+    #[test]
+    fn source_uses_resource_url() {
+        let capabilities = Capabilities::parse(CAPABILITIES).unwrap();
+        let source = capabilities.source("ortho").unwrap();
+
+        assert_eq!(
+            "https://example.com/wmts/ortho/default/GoogleMapsCompatible/2/2/1.png",
+            source.tile_url(TILE_ID)
+        );
+        assert_eq!(0..=2, source.zoom_range());
+        assert_eq!(256, source.tile_size());
+        assert_eq!("Test Provider", source.attribution().text);
+    }
+
+    #[test]
+    fn source_uses_key_value_pairs() {
+        let capabilities = Capabilities::parse(CAPABILITIES).unwrap();
+        let source = capabilities.source("roads").unwrap();
+
+        assert_eq!(
+            "https://example.com/wmts?SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&LAYER=roads\
+            &STYLE=default&FORMAT=image%2Fpng&TILEMATRIXSET=GoogleMapsCompatible&TILEMATRIX=2\
+            &TILEROW=2&TILECOL=1",
+            source.tile_url(TILE_ID)
+        );
+    }
+
+    #[test]
+    fn key_value_pairs_are_escaped() {
+        let capabilities = Capabilities::parse(CAPABILITIES).unwrap();
+        let mut source = capabilities.source("roads").unwrap();
+        source.layer = "roads & rails".to_owned();
+
+        assert!(source
+            .tile_url(TILE_ID)
+            .contains("&LAYER=roads+%26+rails&STYLE=default&"));
+    }
+
+    #[test]
+    fn zooms_without_tile_matrix_are_not_requested() {
+        let capabilities = Capabilities::parse(CAPABILITIES).unwrap();
+        let mut source = capabilities.source("ortho").unwrap();
+        source.tile_matrices.remove(&1);
+
+        assert_eq!(0..=0, source.zoom_range());
+        assert_eq!(None, source.url(TileId { zoom: 1, ..TILE_ID }));
+        assert!(source.url(TILE_ID).is_some());
+    }
+
+    #[test]
+    fn layers_without_web_mercator_are_rejected() {
+        let capabilities = Capabilities::parse(CAPABILITIES).unwrap();
+
+        assert!(matches!(
+            capabilities.source("parcels"),
+            Err(Error::NoWebMercator(_))
+        ));
+        assert!(matches!(
+            capabilities.source("nonexistent"),
+            Err(Error::NoSuchLayer(_))
+        ));
+    }
+
+    #[test]
+    fn capabilities_are_downloaded() {
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("GET", "/wmts")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "REQUEST".to_owned(),
+                "GetCapabilities".to_owned(),
+            ))
+            .with_body(CAPABILITIES)
+            .create();
+
+        let capabilities = block_on(Capabilities::from_url(
+            &format!("{}/wmts?SERVICE=WMTS&REQUEST=GetCapabilities", server.url()),
+            &HttpOptions::default(),
+        ))
+        .unwrap();
+
+        assert_eq!(3, capabilities.layers.len());
+    }
+}
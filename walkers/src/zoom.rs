@@ -19,6 +19,12 @@ impl TryFrom<f32> for Zoom {
     }
 }
 
+impl From<Zoom> for f64 {
+    fn from(zoom: Zoom) -> Self {
+        zoom.0 as f64
+    }
+}
+
 impl Default for Zoom {
     fn default() -> Self {
         Self(16.)
@@ -26,6 +32,7 @@ impl Default for Zoom {
 }
 
 impl Zoom {
+    /// Nearest integer zoom, which is the level of the tiles being drawn.
     pub fn round(&self) -> u8 {
         self.0.round() as u8
     }
@@ -40,11 +47,15 @@ impl Zoom {
         Ok(())
     }
 
-    /// Zoom using a relative value.
+    /// Zoom using a relative value. Zoom is continuous, so it stops exactly at the limit
+    /// instead of ignoring the step which would exceed it.
     pub fn zoom_by(&mut self, value: f32) {
-        if let Ok(new_self) = Self::try_from(self.0 + value) {
-            *self = new_self;
-        }
+        *self = Self::clamped(self.0 + value);
+    }
+
+    /// Nearest valid zoom.
+    pub(crate) fn clamped(value: f32) -> Self {
+        Self(value.clamp(0., 19.))
     }
 }
 
@@ -67,6 +78,20 @@ mod tests {
         assert_eq!(Err(InvalidZoom), zoom.zoom_in());
     }
 
+    #[test]
+    fn test_zooming_by_fractional_value() {
+        let mut zoom = Zoom::try_from(18.).unwrap();
+        zoom.zoom_by(0.75);
+        assert_eq!(18.75, f64::from(zoom));
+        assert_eq!(19, zoom.round());
+
+        zoom.zoom_by(0.5);
+        assert_eq!(19., f64::from(zoom));
+
+        zoom.zoom_by(-20.);
+        assert_eq!(0., f64::from(zoom));
+    }
+
     #[test]
     fn test_zooming_out() {
         let mut zoom = Zoom::try_from(1.).unwrap();